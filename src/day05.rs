@@ -3,6 +3,8 @@ use itertools::Itertools;
 use recap::Recap;
 use serde::Deserialize;
 
+use crate::utils::{write_pnm, PnmFormat};
+
 #[derive(Debug, Deserialize, Recap)]
 #[recap(regex = r"(?P<x1>\d+),(?P<y1>\d+) -> (?P<x2>\d+),(?P<y2>\d+)")]
 pub struct Line {
@@ -26,6 +28,10 @@ impl Line {
         }
         points
     }
+
+    fn is_axis_aligned(&self) -> bool {
+        self.x1 == self.x2 || self.y1 == self.y2
+    }
 }
 
 #[aoc_generator(day5)]
@@ -56,6 +62,42 @@ pub fn part2(lines: &[Line]) -> usize {
         .count()
 }
 
+/// Overlap count per cell over the bounding box of all lines (origin at 0,0)
+pub struct Heatmap {
+    pub counts: Vec<u32>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Heatmap {
+    /// `diagonals = false` only keeps horizontal/vertical lines (part1)
+    pub fn new(lines: &[Line], diagonals: bool) -> Self {
+        let lines = lines
+            .iter()
+            .filter(|l| diagonals || l.is_axis_aligned())
+            .collect::<Vec<_>>();
+        let width = lines.iter().map(|l| l.x1.max(l.x2) + 1).max().unwrap_or(0) as usize;
+        let height = lines.iter().map(|l| l.y1.max(l.y2) + 1).max().unwrap_or(0) as usize;
+        let mut counts = vec![0; width * height];
+        for (x, y) in lines.iter().flat_map(|l| l.to_points()) {
+            counts[y as usize * width + x as usize] += 1;
+        }
+        Heatmap {
+            counts,
+            width,
+            height,
+        }
+    }
+
+    pub fn write_pnm<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        format: PnmFormat,
+    ) -> std::io::Result<()> {
+        write_pnm(w, &self.counts, self.width, format)
+    }
+}
+
 #[cfg(test)]
 mod test_day05 {
     use super::*;
@@ -81,4 +123,30 @@ mod test_day05 {
     fn test_part2() {
         assert_eq!(part2(&input_parser(TESTCASE)), 12)
     }
+
+    #[test]
+    fn test_heatmap() {
+        let lines = input_parser(TESTCASE);
+        let heatmap = Heatmap::new(&lines, true);
+        assert_eq!(heatmap.counts.iter().filter(|c| **c > 1).count(), 12);
+
+        let mut pgm = Vec::new();
+        Heatmap::new(&lines, false)
+            .write_pnm(&mut pgm, PnmFormat::Pgm)
+            .unwrap();
+        assert!(pgm.starts_with(b"P5\n10 10\n255\n"));
+        assert_eq!(pgm.len(), "P5\n10 10\n255\n".len() + 100);
+
+        // Empty cells are black, the most overlapped ones white
+        let mut ppm = Vec::new();
+        Heatmap::new(&lines, false)
+            .write_pnm(&mut ppm, PnmFormat::Ppm)
+            .unwrap();
+        let header = "P6\n10 10\n255\n";
+        assert!(ppm.starts_with(header.as_bytes()));
+        assert_eq!(ppm.len(), header.len() + 300);
+        let colors = ppm[header.len()..].chunks(3).collect::<Vec<_>>();
+        assert!(colors.contains(&[0, 0, 0].as_slice()));
+        assert!(colors.contains(&[255, 255, 255].as_slice()));
+    }
 }
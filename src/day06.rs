@@ -2,6 +2,8 @@ use std::collections::VecDeque;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use crate::utils::{mat_pow_vec, mul_add, Matrix};
+
 #[aoc_generator(day6)]
 pub fn input_parser(input: &str) -> Vec<usize> {
     input.split(',').map(|s| s.parse().unwrap()).collect()
@@ -10,6 +12,90 @@ pub fn input_parser(input: &str) -> Vec<usize> {
 const RESET: usize = 6;
 const NEW: usize = 8;
 
+/// Life-cycle of a lanternfish: a fish whose timer hits 0 goes back to `reset`
+/// and spawns a fish with timer `newborn`. Timers range over `0..=max_timer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LanternfishModel {
+    reset: usize,
+    newborn: usize,
+    max_timer: usize,
+}
+
+impl Default for LanternfishModel {
+    fn default() -> Self {
+        LanternfishModel::new(RESET, NEW)
+    }
+}
+
+impl LanternfishModel {
+    pub fn new(reset: usize, newborn: usize) -> Self {
+        LanternfishModel {
+            reset,
+            newborn,
+            max_timer: reset.max(newborn),
+        }
+    }
+
+    pub fn max_timer(&self) -> usize {
+        self.max_timer
+    }
+
+    /// Number of fish per timer, panicking on a timer above `max_timer`
+    fn counter(&self, fish: &[usize]) -> Vec<usize> {
+        let mut counter = vec![0; self.max_timer + 1];
+        fish.iter().for_each(|f| {
+            assert!(
+                *f <= self.max_timer,
+                "Fish timer {} above {}",
+                f,
+                self.max_timer
+            );
+            counter[*f] += 1;
+        });
+        counter
+    }
+
+    /// Per-timer histogram for each day, starting with day 0 (the initial population)
+    pub fn histograms(&self, fish: &[usize]) -> Histograms {
+        Histograms {
+            model: *self,
+            counter: self.counter(fish),
+        }
+    }
+
+    /// Total population after `days`, tracking spawns per day rather than per timer
+    pub fn population(&self, fish: &[usize], days: usize) -> usize {
+        let mut deq = self.counter(fish);
+        deq.resize(self.max_timer + 1 + days, 0);
+
+        for d in 0..days {
+            let num_spawn = deq[d];
+            deq[d + self.reset + 1] += num_spawn;
+            deq[d + self.newborn + 1] += num_spawn;
+        }
+        deq[days..].iter().sum()
+    }
+}
+
+pub struct Histograms {
+    model: LanternfishModel,
+    counter: Vec<usize>,
+}
+
+impl Iterator for Histograms {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let today = self.counter.clone();
+        let new_gen = self.counter[0];
+        self.counter.rotate_left(1);
+        self.counter[self.model.max_timer] = 0;
+        self.counter[self.model.reset] += new_gen;
+        self.counter[self.model.newborn] += new_gen;
+        Some(today)
+    }
+}
+
 fn simulate_lanternfish(fish: &[usize], days: usize) -> usize {
     let mut fish_counter = [0; NEW + 1];
     fish.iter().for_each(|f| {
@@ -80,17 +166,44 @@ pub fn part2_fred_deque(input: &[usize]) -> usize {
 #[aoc(day6, part2, fred_array)]
 pub fn part2_fred_array(input: &[usize]) -> usize {
     const DAYS: usize = 256;
-    let mut deq = [0; 9 + DAYS];
+    let mut deq = [0; NEW + 1 + DAYS];
     input.iter().for_each(|f| {
         deq[*f] += 1;
     });
 
     for d in 0..DAYS {
         let num_spawn = deq[d];
-        deq[d + 7] += num_spawn;
-        deq[d + 9] += num_spawn;
+        deq[d + RESET + 1] += num_spawn;
+        deq[d + NEW + 1] += num_spawn;
+    }
+    ((DAYS)..(DAYS + NEW + 1)).map(|x| deq[x]).sum()
+}
+
+/// `m[to][from]`: how many fish with timer `to` a fish with timer `from` yields the next day
+fn transition_matrix() -> Matrix {
+    let mut m = vec![vec![0; NEW + 1]; NEW + 1];
+    for d in 1..NEW + 1 {
+        m[d - 1][d] = 1;
     }
-    ((DAYS)..(DAYS + 9)).map(|x| deq[x]).sum()
+    m[RESET][0] = 1;
+    m[NEW][0] = 1;
+    m
+}
+
+/// Population after `days` using fast exponentiation of the 9x9 transition matrix: O(log(days)).
+/// Without `modulus` the count is exact, and `None` once it overflows `u128` (around day 1000).
+/// With it the count is reduced modulo `modulus`, see [`mat_pow_vec`].
+pub fn lanternfish_matpow(fish: &[usize], days: u64, modulus: Option<u128>) -> Option<u128> {
+    let mut counter = vec![0; NEW + 1];
+    fish.iter().for_each(|f| counter[*f] += 1);
+    mat_pow_vec(&transition_matrix(), days, &counter, modulus)?
+        .into_iter()
+        .try_fold(0, |total, count| mul_add(total, count, 1, modulus))
+}
+
+#[aoc(day6, part2, matrix)]
+pub fn part2_matrix(input: &[usize]) -> Option<u128> {
+    lanternfish_matpow(input, 256, None)
 }
 
 #[cfg(test)]
@@ -108,4 +221,52 @@ mod test_day06 {
     fn test_part2() {
         assert_eq!(part2(&input_parser(TESTCASE)), 26_984_457_539)
     }
+
+    #[test]
+    fn test_model() {
+        let input = input_parser(TESTCASE);
+        let model = LanternfishModel::default();
+        assert_eq!(model.population(&input, 256), 26_984_457_539);
+        let day18 = model.histograms(&input).nth(18).unwrap();
+        assert_eq!(day18.iter().sum::<usize>(), 26);
+        assert_eq!(day18, vec![3, 5, 3, 2, 2, 1, 5, 1, 4]);
+
+        let slow = LanternfishModel::new(9, 12);
+        assert_eq!(
+            slow.histograms(&input)
+                .nth(100)
+                .unwrap()
+                .iter()
+                .sum::<usize>(),
+            slow.population(&input, 100)
+        );
+        assert_eq!(slow.max_timer(), 12);
+    }
+
+    #[test]
+    #[should_panic(expected = "Fish timer 9 above 8")]
+    fn test_model_timer() {
+        LanternfishModel::default().population(&[3, 9], 10);
+    }
+
+    #[test]
+    fn test_matpow() {
+        let input = input_parser(TESTCASE);
+        assert_eq!(part2_matrix(&input), Some(26_984_457_539));
+        assert_eq!(
+            lanternfish_matpow(&input, 256, Some(1_000_000_007)),
+            Some(26_984_457_539 % 1_000_000_007)
+        );
+
+        // Exact counts overflow u128 after about 1000 days, modular ones don't
+        assert!(lanternfish_matpow(&input, 900, None).is_some());
+        assert_eq!(lanternfish_matpow(&input, 2000, None), None);
+        assert!(lanternfish_matpow(&input, 1 << 40, Some(1_000_000_007)).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Modulus should be in 1..2^64")]
+    fn test_matpow_modulus() {
+        lanternfish_matpow(&input_parser(TESTCASE), 256, Some(1 << 64));
+    }
 }
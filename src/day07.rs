@@ -5,30 +5,186 @@ pub fn input_parser(input: &str) -> Vec<i32> {
     input.split(',').map(|s| s.parse().unwrap()).collect()
 }
 
-#[aoc(day7, part1, median)]
-pub fn part1(input: &[i32]) -> i32 {
-    let mut crabs = input.to_vec();
-    crabs.sort_unstable();
-    let median = crabs[crabs.len() / 2];
-    crabs.iter().map(|c| (*c - median).abs()).sum()
+/// Fuel for a crab to move `diff` steps in part1
+pub fn linear_cost(diff: i64) -> i64 {
+    diff
+}
+
+/// Fuel for a crab to move `diff` steps in part2: 1 + 2 + ... + diff
+pub fn triangular_cost(diff: i64) -> i64 {
+    diff * (diff + 1) / 2
+}
+
+/// Steps between a crab and a position, without overflowing for far apart ones
+fn distance(crab: i32, pos: i32) -> i64 {
+    (crab as i64 - pos as i64).abs()
+}
+
+/// Find the alignment position minimising the total fuel, returned as `(position, fuel)`.
+/// `cost(crab, pos)` must be convex in `pos` for every crab so that the total is convex:
+/// the optimum is then the first position where the discrete derivative is non-negative,
+/// found by binary search between the leftmost and rightmost crab.
+pub fn align<F>(crabs: &[i32], cost: F) -> Option<(i32, i64)>
+where
+    F: Fn(i32, i32) -> i64,
+{
+    let total = |pos: i32| crabs.iter().map(|c| cost(*c, pos)).sum::<i64>();
+    let lo = *crabs.iter().min()?;
+    let hi = *crabs.iter().max()?;
+    Some(minimise_convex(lo, hi, total))
+}
+
+/// Binary search on the discrete derivative of a convex `total` over `lo..=hi`
+fn minimise_convex<F: Fn(i32) -> i64>(mut lo: i32, mut hi: i32, total: F) -> (i32, i64) {
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if total(mid + 1) - total(mid) >= 0 {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    (lo, total(lo))
+}
+
+/// A crab burning `weight` times the fuel of a standard crab, weights are non-negative
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crab {
+    pub pos: i32,
+    pub weight: i64,
+}
+
+/// Comma separated `pos` or `pos:weight`, weight defaulting to 1.
+/// Negative weights would make the total fuel non-convex, so they are rejected.
+pub fn weighted_input_parser(input: &str) -> Vec<Crab> {
+    input
+        .split(',')
+        .map(|s| match s.split_once(':') {
+            Some((pos, weight)) => {
+                let weight = weight.parse().unwrap();
+                assert!(weight >= 0, "Crab weights should be non-negative");
+                Crab {
+                    pos: pos.parse().unwrap(),
+                    weight,
+                }
+            }
+            None => Crab {
+                pos: s.parse().unwrap(),
+                weight: 1,
+            },
+        })
+        .collect()
+}
+
+/// Same as [`align`] with every crab's `cost(distance)` scaled by its weight
+pub fn align_weighted<F>(crabs: &[Crab], cost: F) -> Option<(i32, i64)>
+where
+    F: Fn(i64) -> i64,
+{
+    let total = |pos: i32| {
+        crabs
+            .iter()
+            .map(|c| c.weight * cost((c.pos - pos).abs() as i64))
+            .sum::<i64>()
+    };
+    let lo = crabs.iter().map(|c| c.pos).min()?;
+    let hi = crabs.iter().map(|c| c.pos).max()?;
+    Some(minimise_convex(lo, hi, total))
+}
+
+/// Choose up to `k` meeting points minimising the total weighted linear fuel (1D k-medians).
+/// Returns the meeting points from left to right and the total fuel.
+///
+/// Once sorted, every meeting point gathers a contiguous run of crabs, whose best point is its
+/// weighted median. `dp[j][e]` is the cheapest way to gather the first `e` crabs at `j` points.
+pub fn align_k(crabs: &[Crab], k: usize) -> Option<(Vec<i32>, i64)> {
+    if crabs.is_empty() || k == 0 {
+        return None;
+    }
+    assert!(
+        crabs.iter().all(|c| c.weight >= 0),
+        "Crab weights should be non-negative"
+    );
+    let mut crabs = crabs.to_vec();
+    crabs.sort_unstable_by_key(|c| c.pos);
+    let n = crabs.len();
+    let k = k.min(n);
+
+    // Prefix sums of weights and weighted positions
+    let mut w = vec![0; n + 1];
+    let mut wx = vec![0; n + 1];
+    for (i, c) in crabs.iter().enumerate() {
+        w[i + 1] = w[i] + c.weight;
+        wx[i + 1] = wx[i] + c.weight * c.pos as i64;
+    }
+
+    // segment[s][e]: (median, fuel) to gather crabs s..e (exclusive) at their weighted median.
+    // For a fixed start, the median only moves right as the segment grows.
+    let mut segment = vec![vec![(0, 0); n + 1]; n];
+    for s in 0..n {
+        let mut m = s;
+        for e in s + 1..=n {
+            while 2 * (w[m + 1] - w[s]) < w[e] - w[s] {
+                m += 1;
+            }
+            let x = crabs[m].pos as i64;
+            let left = x * (w[m + 1] - w[s]) - (wx[m + 1] - wx[s]);
+            let right = (wx[e] - wx[m + 1]) - x * (w[e] - w[m + 1]);
+            segment[s][e] = (crabs[m].pos, left + right);
+        }
+    }
+
+    let mut dp = vec![vec![i64::MAX; n + 1]; k + 1];
+    let mut split = vec![vec![0; n + 1]; k + 1];
+    dp[0][0] = 0;
+    for j in 1..=k {
+        for e in 1..=n {
+            for s in j - 1..e {
+                if dp[j - 1][s] == i64::MAX {
+                    continue;
+                }
+                let fuel = dp[j - 1][s] + segment[s][e].1;
+                if fuel < dp[j][e] {
+                    dp[j][e] = fuel;
+                    split[j][e] = s;
+                }
+            }
+        }
+    }
+
+    // More points never cost more, but only use as many as needed
+    let best = (1..=k).min_by_key(|j| (dp[*j][n], *j))?;
+    let mut targets = Vec::with_capacity(best);
+    let mut e = n;
+    for j in (1..=best).rev() {
+        let s = split[j][e];
+        targets.push(segment[s][e].0);
+        e = s;
+    }
+    targets.reverse();
+    Some((targets, dp[best][n]))
+}
+
+#[aoc(day7, part1, convex)]
+pub fn part1(input: &[i32]) -> i64 {
+    let (_, fuel) = align(input, |c, pos| linear_cost(distance(c, pos))).unwrap();
+    fuel
 }
 
 #[aoc(day7, part2, dumb)]
-pub fn part2_dumb(input: &[i32]) -> Option<i32> {
-    let mut crabs = input.to_vec();
-    crabs.sort_unstable();
-    let start = *crabs.first().unwrap();
-    let end = *crabs.last().unwrap();
+pub fn part2_dumb(input: &[i32]) -> Option<i64> {
+    let start = *input.iter().min()?;
+    let end = *input.iter().max()?;
+    let fuel = |pos: i32| {
+        input
+            .iter()
+            .map(|c| triangular_cost(distance(*c, pos)))
+            .sum::<i64>()
+    };
     let mut min_fuel = None;
 
     for pos in start..=end {
-        let fuel: i32 = crabs
-            .iter()
-            .map(|c| {
-                let diff = (*c - pos).abs();
-                diff * (diff + 1) / 2
-            })
-            .sum();
+        let fuel = fuel(pos);
         min_fuel = match min_fuel {
             None => Some(fuel),
             Some(d) if d > fuel => Some(fuel),
@@ -38,19 +194,10 @@ pub fn part2_dumb(input: &[i32]) -> Option<i32> {
     min_fuel
 }
 
-#[aoc(day7, part2, mean)]
-pub fn part2(input: &[i32]) -> i32 {
-    let floored_mean = (input.iter().sum::<i32>() as f32 / input.len() as f32).floor() as i32;
-    let fuel = |pos: i32| {
-        input
-            .iter()
-            .map(|c| {
-                let diff = (*c - pos).abs();
-                diff * (diff + 1) / 2
-            })
-            .sum()
-    };
-    std::cmp::min(fuel(floored_mean), fuel(floored_mean + 1))
+#[aoc(day7, part2, convex)]
+pub fn part2(input: &[i32]) -> i64 {
+    let (_, fuel) = align(input, |c, pos| triangular_cost(distance(c, pos))).unwrap();
+    fuel
 }
 
 #[cfg(test)]
@@ -66,6 +213,46 @@ mod test_day07 {
 
     #[test]
     fn test_part2() {
-        assert_eq!(part2(&input_parser(TESTCASE)), 168)
+        assert_eq!(part2(&input_parser(TESTCASE)), 168);
+        assert_eq!(part2_dumb(&input_parser(TESTCASE)), Some(168));
+
+        // Fuel above i32::MAX
+        assert_eq!(part2(&[0, 100_000]), 2_500_050_000);
+        assert_eq!(part2_dumb(&[0, 100_000]), Some(2_500_050_000));
+    }
+
+    #[test]
+    fn test_align() {
+        let crabs = input_parser(TESTCASE);
+        assert_eq!(
+            align(&crabs, |c, pos| triangular_cost(distance(c, pos))),
+            Some((5, 168))
+        );
+        // Quadratic cost is minimised at the mean (4.9)
+        assert_eq!(
+            align(&crabs, |c, pos| ((c - pos) as i64).pow(2)),
+            Some((5, 291))
+        );
+    }
+
+    #[test]
+    fn test_weighted() {
+        let crabs = weighted_input_parser(TESTCASE);
+        assert_eq!(align_weighted(&crabs, linear_cost), Some((2, 37)));
+        assert_eq!(align_k(&crabs, 1), Some((vec![2], 37)));
+        // {0,1,1,2,2,2,4,7} at 2, {14,16} at 14
+        assert_eq!(align_k(&crabs, 2), Some((vec![2, 14], 13)));
+        assert_eq!(align_k(&crabs, 7).unwrap().1, 0);
+        assert_eq!(align_k(&crabs, 100).unwrap().0.len(), 7);
+
+        let heavy = weighted_input_parser("0:10,10:1");
+        assert_eq!(align_weighted(&heavy, linear_cost), Some((0, 10)));
+        assert_eq!(align_k(&heavy, 1), Some((vec![0], 10)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Crab weights should be non-negative")]
+    fn test_negative_weight() {
+        weighted_input_parser("0:1,5:-3");
     }
 }
@@ -32,13 +32,14 @@ pub fn input_parser(input: &str) -> Vec<Input> {
     input
         .lines()
         .map(|s| {
-            let (signal, output) = s.split_once(" | ").unwrap();
+            // Any number of observed signals and outputs
+            let (signal, output) = s.split_once('|').unwrap();
             let signal = signal
-                .splitn(10, ' ')
+                .split_whitespace()
                 .map(|s| s.chars().collect::<HashSet<_>>())
                 .collect::<Vec<_>>();
             let output = output
-                .splitn(4, ' ')
+                .split_whitespace()
                 .map(|s| s.chars().collect::<HashSet<_>>())
                 .collect::<Vec<_>>();
             Input {
@@ -125,6 +126,349 @@ pub fn part2(inputs: &[Input]) -> usize {
         .sum()
 }
 
+/// Patterns as masks of segments (`a` is bit 0)
+pub struct InputMask {
+    signals: [u8; 10],
+    outputs: [u8; 4],
+}
+
+fn pattern_mask(pattern: &str) -> u8 {
+    pattern.bytes().fold(0, |mask, b| mask | 1 << (b - b'a'))
+}
+
+#[aoc_generator(day8, part2, bitmask)]
+pub fn input_parser_bitmask(input: &str) -> Vec<InputMask> {
+    input
+        .lines()
+        .map(|s| {
+            let (signal, output) = s.split_once(" | ").unwrap();
+            let mut signals = [0; 10];
+            signal
+                .split(' ')
+                .zip(signals.iter_mut())
+                .for_each(|(p, m)| *m = pattern_mask(p));
+            let mut outputs = [0; 4];
+            output
+                .split(' ')
+                .zip(outputs.iter_mut())
+                .for_each(|(p, m)| *m = pattern_mask(p));
+            InputMask { signals, outputs }
+        })
+        .collect()
+}
+
+/// Same rules as [`match_digits`] on masks, as a lookup table from pattern to digit
+fn digit_table(signals: &[u8; 10]) -> [u8; 128] {
+    let with_len = |len| *signals.iter().find(|s| s.count_ones() == len).unwrap();
+    let one = with_len(2);
+    let four = with_len(4);
+
+    let mut table = [0; 128];
+    for signal in signals {
+        let intersect = |digit: u8| (signal & digit).count_ones();
+        table[*signal as usize] = match signal.count_ones() {
+            2 => 1,
+            3 => 7,
+            4 => 4,
+            7 => 8,
+            5 if intersect(one) == 2 => 3,
+            5 if intersect(four) == 2 => 2,
+            5 => 5,
+            6 if intersect(one) == 1 => 6,
+            6 if intersect(four) == 4 => 9,
+            _ => 0,
+        };
+    }
+    table
+}
+
+#[aoc(day8, part2, bitmask)]
+pub fn part2_bitmask(inputs: &[InputMask]) -> usize {
+    inputs
+        .iter()
+        .map(|input| {
+            let table = digit_table(&input.signals);
+            input
+                .outputs
+                .iter()
+                .fold(0, |sum, output| sum * 10 + table[*output as usize] as usize)
+        })
+        .sum()
+}
+
+/// Segments lit for each digit of a display, one bit per segment (`a` is bit 0)
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pub segments: usize,
+    pub digits: Vec<u32>,
+}
+
+impl Layout {
+    /// `digits[d]` lists the segments of digit `d` as letters, e.g. "cf" for 1 on 7 segments.
+    /// Letters must be among the first `segments` ones.
+    pub fn new(segments: usize, digits: &[&str]) -> Result<Self, WiringError> {
+        assert!(segments <= 32);
+        let digits = digits
+            .iter()
+            .map(|d| letters_mask(d.chars(), segments))
+            .collect::<Result<_, _>>()?;
+        Ok(Layout { segments, digits })
+    }
+
+    pub fn seven_segment() -> Self {
+        Layout::new(
+            7,
+            &[
+                "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg",
+                "abcdfg",
+            ],
+        )
+        .unwrap()
+    }
+
+    /// Digit displayed by `pattern` (a mask of wires) under `wiring` (wire -> segment)
+    pub fn decode(&self, wiring: &[usize], pattern: u32) -> Option<usize> {
+        let segments = (0..wiring.len())
+            .filter(|w| pattern & 1 << w != 0)
+            .fold(0, |mask, w| mask | 1 << wiring[w]);
+        self.digits.iter().position(|d| *d == segments)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WiringError {
+    /// A pattern or a layout digit uses a letter the display has no wire or segment for
+    UnknownWire(char),
+    /// No wiring maps every pattern onto a distinct digit of the layout
+    Contradiction,
+    /// Several wirings fit the patterns
+    Ambiguous,
+}
+
+/// Mask of letters (`a` is bit 0), which must be among the first `count` ones
+fn letters_mask(mut letters: impl Iterator<Item = char>, count: usize) -> Result<u32, WiringError> {
+    letters.try_fold(0, |mask, c| match (c as u32).checked_sub('a' as u32) {
+        Some(w) if (w as usize) < count => Ok(mask | 1 << w),
+        _ => Err(WiringError::UnknownWire(c)),
+    })
+}
+
+/// Mask of the wires lit in a pattern (`a` is bit 0)
+pub fn wire_mask(pattern: &HashSet<char>, layout: &Layout) -> Result<u32, WiringError> {
+    letters_mask(pattern.iter().copied(), layout.segments)
+}
+
+/// Enumerate up to `limit` wirings (wire -> segment) under which every pattern is a distinct digit.
+///
+/// Patterns are assigned to digits by backtracking, most constrained first. Each assignment
+/// narrows the candidate segments of each wire: wires of the pattern must land on the digit's
+/// segments, the others outside of them. Once all patterns are placed, the remaining freedom
+/// is enumerated as a matching of wires to segments.
+fn wirings(layout: &Layout, patterns: &[u32], limit: usize) -> Vec<Vec<usize>> {
+    let mut patterns = patterns.to_vec();
+    patterns.sort_unstable();
+    patterns.dedup();
+    let options = |p: &u32| {
+        layout
+            .digits
+            .iter()
+            .filter(|d| d.count_ones() == p.count_ones())
+            .count()
+    };
+    patterns.sort_by_key(options);
+
+    let all = if layout.segments == 32 {
+        u32::MAX
+    } else {
+        (1 << layout.segments) - 1
+    };
+    let mut used = vec![false; layout.digits.len()];
+    let mut found = Vec::new();
+    assign_patterns(
+        layout,
+        &patterns,
+        &mut used,
+        vec![all; layout.segments],
+        limit,
+        &mut found,
+    );
+    found
+}
+
+fn assign_patterns(
+    layout: &Layout,
+    patterns: &[u32],
+    used: &mut [bool],
+    candidates: Vec<u32>,
+    limit: usize,
+    found: &mut Vec<Vec<usize>>,
+) {
+    let (pattern, rest) = match patterns.split_first() {
+        Some(split) => split,
+        None => {
+            let mut wiring = Vec::with_capacity(candidates.len());
+            match_wires(&candidates, 0, &mut wiring, limit, found);
+            return;
+        }
+    };
+
+    for (d, digit) in layout.digits.iter().enumerate() {
+        if used[d] || digit.count_ones() != pattern.count_ones() {
+            continue;
+        }
+        let narrowed = candidates
+            .iter()
+            .enumerate()
+            .map(|(w, c)| match pattern & 1 << w {
+                0 => c & !digit,
+                _ => c & digit,
+            })
+            .collect::<Vec<_>>();
+        if narrowed.contains(&0) {
+            continue;
+        }
+        used[d] = true;
+        assign_patterns(layout, rest, used, narrowed, limit, found);
+        used[d] = false;
+        if found.len() >= limit {
+            return;
+        }
+    }
+}
+
+/// Assign each wire a distinct segment among its candidates. A display has one wire per
+/// segment, so `candidates` has one entry per segment.
+fn match_wires(
+    candidates: &[u32],
+    taken: u32,
+    wiring: &mut Vec<usize>,
+    limit: usize,
+    found: &mut Vec<Vec<usize>>,
+) {
+    let segments = candidates.len();
+    let w = wiring.len();
+    if w == segments {
+        found.push(wiring.clone());
+        return;
+    }
+    for segment in 0..segments {
+        if candidates[w] & !taken & 1 << segment == 0 {
+            continue;
+        }
+        wiring.push(segment);
+        match_wires(candidates, taken | 1 << segment, wiring, limit, found);
+        wiring.pop();
+        if found.len() >= limit {
+            return;
+        }
+    }
+}
+
+/// General alternative to [`match_digits`]: the unique wiring (wire -> segment) of `layout`
+/// under which every signal is a distinct digit
+pub fn solve_wiring(layout: &Layout, signals: &[HashSet<char>]) -> Result<Vec<usize>, WiringError> {
+    let patterns = signals
+        .iter()
+        .map(|s| wire_mask(s, layout))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut found = wirings(layout, &patterns, 2);
+    match found.len() {
+        0 => Err(WiringError::Contradiction),
+        1 => Ok(found.remove(0)),
+        _ => Err(WiringError::Ambiguous),
+    }
+}
+
+/// Wirings consistent with a partially observed line, and what they say about the outputs
+#[derive(Debug)]
+pub struct Decoding {
+    pub wirings: Vec<Vec<usize>>,
+    /// More wirings than the limit fit: `wirings` and `outputs` are only a sample
+    pub truncated: bool,
+    /// Digits each output may show, a single one when it is uniquely determined
+    pub outputs: Vec<Vec<usize>>,
+}
+
+impl Decoding {
+    /// Whether every output digit is uniquely determined, which can't be told when truncated
+    pub fn is_trusted(&self) -> bool {
+        !self.truncated && self.outputs.iter().all(|digits| digits.len() == 1)
+    }
+
+    /// Displayed number, only if it can be trusted
+    pub fn value(&self) -> Option<usize> {
+        if !self.is_trusted() {
+            return None;
+        }
+        self.outputs
+            .iter()
+            .try_fold(0, |sum, digits| match digits[..] {
+                [digit] => Some(sum * 10 + digit),
+                _ => None,
+            })
+    }
+}
+
+/// Decode a line where some signals may be missing. Outputs are observations too, so they also
+/// constrain the wiring. With few observations the number of wirings grows quickly
+/// (up to `segments!`), so at most `limit` of them are enumerated.
+pub fn decode_partial(
+    layout: &Layout,
+    input: &Input,
+    limit: usize,
+) -> Result<Decoding, WiringError> {
+    let signals = input
+        .signals
+        .iter()
+        .map(|s| wire_mask(s, layout))
+        .collect::<Result<Vec<_>, _>>()?;
+    let outputs = input
+        .outputs
+        .iter()
+        .map(|s| wire_mask(s, layout))
+        .collect::<Result<Vec<_>, _>>()?;
+    let patterns = [&signals[..], &outputs[..]].concat();
+
+    let mut wirings = wirings(layout, &patterns, limit.saturating_add(1));
+    if wirings.is_empty() {
+        return Err(WiringError::Contradiction);
+    }
+    let truncated = wirings.len() > limit;
+    wirings.truncate(limit);
+    let outputs = outputs
+        .iter()
+        .map(|pattern| {
+            let mut digits = wirings
+                .iter()
+                .filter_map(|wiring| layout.decode(wiring, *pattern))
+                .collect::<Vec<_>>();
+            digits.sort_unstable();
+            digits.dedup();
+            digits
+        })
+        .collect();
+    Ok(Decoding {
+        wirings,
+        truncated,
+        outputs,
+    })
+}
+
+#[aoc(day8, part2, solver)]
+pub fn part2_solver(inputs: &[Input]) -> usize {
+    let layout = Layout::seven_segment();
+    inputs
+        .iter()
+        .map(|input| {
+            let wiring = solve_wiring(&layout, &input.signals).expect("Wiring");
+            input.outputs.iter().fold(0, |sum, output| {
+                let pattern = wire_mask(output, &layout).unwrap();
+                sum * 10 + layout.decode(&wiring, pattern).expect("Match")
+            })
+        })
+        .sum()
+}
+
 #[cfg(test)]
 mod test_day08 {
     use super::*;
@@ -150,4 +494,83 @@ gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce
     fn test_part2() {
         assert_eq!(part2(&input_parser(TESTCASE)), 61229)
     }
+
+    #[test]
+    fn test_part2_bitmask() {
+        assert_eq!(part2_bitmask(&input_parser_bitmask(TESTCASE)), 61229)
+    }
+
+    #[test]
+    fn test_solver() {
+        assert_eq!(part2_solver(&input_parser(TESTCASE)), 61229);
+
+        let layout = Layout::seven_segment();
+        let signals = |s: &str| {
+            s.split(' ')
+                .map(|p| p.chars().collect())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            solve_wiring(&layout, &signals("ab")),
+            Err(WiringError::Ambiguous)
+        );
+        assert_eq!(
+            solve_wiring(&layout, &signals("ab cd")),
+            Err(WiringError::Contradiction)
+        );
+        assert_eq!(
+            solve_wiring(&layout, &signals("ab abz")),
+            Err(WiringError::UnknownWire('z'))
+        );
+
+        // Layout letters beyond the segments, or not letters at all
+        assert_eq!(
+            Layout::new(3, &["ab", "ad"]).unwrap_err(),
+            WiringError::UnknownWire('d')
+        );
+        assert_eq!(
+            Layout::new(3, &["A"]).unwrap_err(),
+            WiringError::UnknownWire('A')
+        );
+        assert_eq!(Layout::new(3, &["a", "bc"]).unwrap().digits, vec![1, 6]);
+    }
+
+    #[test]
+    fn test_partial() {
+        let layout = Layout::seven_segment();
+        let full = input_parser(TESTCASE);
+        let decoding = decode_partial(&layout, &full[0], 100).unwrap();
+        assert_eq!(decoding.wirings.len(), 1);
+        assert_eq!(decoding.value(), Some(8394));
+
+        // Only 1 and 7 seen: a 5-segment output without the 1 is either a 2 or a 5
+        let partial = input_parser("be edb | fdgacbe dbgaf be");
+        let decoding = decode_partial(&layout, &partial[0], 100).unwrap();
+        assert!(decoding.wirings.len() > 1);
+        assert!(!decoding.is_trusted());
+        assert_eq!(decoding.outputs[0], vec![8]);
+        assert_eq!(decoding.outputs[1], vec![2, 5]);
+        assert_eq!(decoding.outputs[2], vec![1]);
+        assert_eq!(decoding.value(), None);
+        assert!(!decoding.truncated);
+
+        // A single output barely constrains a 16 segment display: 15! wirings
+        let digits = (1..=10)
+            .map(|n| "abcdefghijklmnop"[..n].to_string())
+            .collect::<Vec<_>>();
+        let digits = digits.iter().map(|d| d.as_str()).collect::<Vec<_>>();
+        let large = Layout::new(16, &digits).unwrap();
+        let decoding = decode_partial(&large, &input_parser("| p")[0], 1000).unwrap();
+        assert!(decoding.truncated);
+        assert_eq!(decoding.wirings.len(), 1000);
+        assert_eq!(decoding.outputs, vec![vec![0]]);
+        assert!(!decoding.is_trusted());
+        assert_eq!(decoding.value(), None);
+
+        let contradiction = input_parser("be cg | ab");
+        assert_eq!(
+            decode_partial(&layout, &contradiction[0], 100).unwrap_err(),
+            WiringError::Contradiction
+        );
+    }
 }
@@ -1,23 +1,106 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use hashbrown::{HashMap, HashSet};
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, VecDeque};
+use termion::color;
 
 type HeightMap = HashMap<(i32, i32), i32>;
 
+/// One digit per cell, or whitespace separated heights (allowing heights above 9)
+/// if any row contains whitespace between cells. The mode is the same for all rows.
 #[aoc_generator(day9)]
 pub fn input_parser(input: &str) -> HeightMap {
+    if input
+        .lines()
+        .any(|row| row.trim().contains(char::is_whitespace))
+    {
+        return separated_parser(input);
+    }
+    input
+        .lines()
+        .enumerate()
+        .flat_map(|(x, row)| {
+            row.trim()
+                .chars()
+                .enumerate()
+                .map(move |(y, h)| ((x as i32, y as i32), h.to_digit(10).unwrap() as i32))
+        })
+        .collect()
+}
+
+/// Whitespace separated heights, e.g. for a single column grid which can't be told
+/// apart from a grid of digits
+pub fn separated_parser(input: &str) -> HeightMap {
     input
         .lines()
         .enumerate()
         .flat_map(|(x, row)| {
-            row.chars()
+            row.split_whitespace()
                 .enumerate()
-                .map(move |(y, height)| ((x as i32, y as i32), height.to_digit(10).unwrap() as i32))
+                .map(move |(y, h)| ((x as i32, y as i32), h.parse().unwrap()))
         })
         .collect()
 }
 
 const NEIGHBORS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const NEIGHBORS_DIAG: [(i32, i32); 8] = [
+    (-1, 0),
+    (1, 0),
+    (0, -1),
+    (0, 1),
+    (-1, -1),
+    (-1, 1),
+    (1, -1),
+    (1, 1),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    Eight,
+}
+
+impl Connectivity {
+    fn neighbors(&self) -> &'static [(i32, i32)] {
+        match self {
+            Connectivity::Four => &NEIGHBORS,
+            Connectivity::Eight => &NEIGHBORS_DIAG,
+        }
+    }
+}
+
+/// Heightmap analysis settings: which cells are adjacent and from which height a cell is a wall
+#[derive(Clone, Copy, Debug)]
+pub struct Terrain {
+    pub connectivity: Connectivity,
+    pub wall: i32,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain {
+            connectivity: Connectivity::Four,
+            wall: 9,
+        }
+    }
+}
+
+impl Terrain {
+    /// Cells strictly lower than all their neighbors, with their risk level (height + 1)
+    pub fn low_points(&self, hm: &HeightMap) -> Vec<((i32, i32), i32)> {
+        let mut low_points = hm
+            .iter()
+            .filter(|((x, y), height)| {
+                self.connectivity
+                    .neighbors()
+                    .iter()
+                    .all(|(dx, dy)| hm.get(&(x + dx, y + dy)).map_or(true, |n| n > height))
+            })
+            .map(|(pos, height)| (*pos, height + 1))
+            .collect::<Vec<_>>();
+        low_points.sort_unstable();
+        low_points
+    }
+}
 
 #[aoc(day9, part1)]
 pub fn part1(hm: &HeightMap) -> i32 {
@@ -59,7 +142,102 @@ pub fn part2(hm: &HeightMap) -> usize {
         })
         .collect::<BinaryHeap<_>>();
 
-    basin_sizes.iter().take(3).product()
+    basin_sizes.into_sorted_vec().iter().rev().take(3).product()
+}
+
+/// Every non-wall cell labelled with the id of its basin, basins being delimited by walls
+pub struct BasinMap {
+    pub width: usize,
+    pub height: usize,
+    /// Basin id per cell (row-major), `None` on walls
+    pub labels: Vec<Option<usize>>,
+    /// Size of each basin by id
+    pub sizes: Vec<usize>,
+    /// Lowest cell of each basin by id
+    pub low_points: Vec<(i32, i32)>,
+}
+
+impl BasinMap {
+    /// Basins delimited by 9s, with 4-connectivity
+    pub fn new(hm: &HeightMap) -> Self {
+        BasinMap::with_terrain(hm, &Terrain::default())
+    }
+
+    /// Iterative BFS from each unlabelled cell, so large maps cannot overflow the stack
+    pub fn with_terrain(hm: &HeightMap, terrain: &Terrain) -> Self {
+        let height = hm.keys().map(|(x, _)| *x + 1).max().unwrap_or(0) as usize;
+        let width = hm.keys().map(|(_, y)| *y + 1).max().unwrap_or(0) as usize;
+        let mut labels = vec![None; width * height];
+        let mut sizes = Vec::new();
+        let mut low_points = Vec::new();
+        let idx = |x: i32, y: i32| x as usize * width + y as usize;
+
+        let mut queue = VecDeque::new();
+        for x in 0..height as i32 {
+            for y in 0..width as i32 {
+                match hm.get(&(x, y)) {
+                    Some(h) if *h < terrain.wall && labels[idx(x, y)].is_none() => {}
+                    _ => continue,
+                }
+                let id = sizes.len();
+                let mut size = 0;
+                let mut low_point = (x, y);
+                labels[idx(x, y)] = Some(id);
+                queue.push_back((x, y));
+                while let Some((x, y)) = queue.pop_front() {
+                    size += 1;
+                    if hm[&(x, y)] < hm[&low_point] {
+                        low_point = (x, y);
+                    }
+                    for (dx, dy) in terrain.connectivity.neighbors() {
+                        let (nx, ny) = (x + dx, y + dy);
+                        match hm.get(&(nx, ny)) {
+                            Some(nh) if *nh < terrain.wall && labels[idx(nx, ny)].is_none() => {
+                                labels[idx(nx, ny)] = Some(id);
+                                queue.push_back((nx, ny));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                sizes.push(size);
+                low_points.push(low_point);
+            }
+        }
+
+        BasinMap {
+            width,
+            height,
+            labels,
+            sizes,
+            low_points,
+        }
+    }
+
+    /// Terminal rendering with one colour per basin and blank walls
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        for row in self.labels.chunks(self.width.max(1)) {
+            for label in row {
+                match label {
+                    Some(id) => {
+                        let c = color::AnsiValue(16 + (id * 37 % 216) as u8);
+                        s.push_str(&format!("{}█", color::Fg(c)));
+                    }
+                    None => s.push(' '),
+                }
+            }
+            s.push_str(&format!("{}\n", color::Fg(color::Reset)));
+        }
+        s
+    }
+}
+
+#[aoc(day9, part2, labelling)]
+pub fn part2_labelling(hm: &HeightMap) -> usize {
+    let mut sizes = BasinMap::new(hm).sizes;
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    sizes.iter().take(3).product()
 }
 
 #[cfg(test)]
@@ -82,4 +260,51 @@ mod test_day09 {
     fn test_part2() {
         assert_eq!(part2(&input_parser(TESTCASE)), 1134)
     }
+
+    #[test]
+    fn test_basin_map() {
+        let hm = input_parser(TESTCASE);
+        assert_eq!(part2_labelling(&hm), 1134);
+
+        let basins = BasinMap::new(&hm);
+        assert_eq!(basins.sizes, vec![3, 9, 14, 9]);
+        assert_eq!(basins.low_points, vec![(0, 1), (0, 9), (2, 2), (4, 6)]);
+        assert_eq!(basins.labels[0], Some(0));
+        assert_eq!(basins.labels[2], None);
+    }
+
+    #[test]
+    fn test_terrain() {
+        let hm = input_parser(TESTCASE);
+        let low_points = Terrain::default().low_points(&hm);
+        assert_eq!(low_points.iter().map(|(_, risk)| risk).sum::<i32>(), 15);
+        assert_eq!(low_points[0], ((0, 1), 2));
+
+        let diagonal = Terrain {
+            connectivity: Connectivity::Eight,
+            wall: 9,
+        };
+        // All four basins touch diagonally through gaps in the walls
+        assert_eq!(BasinMap::with_terrain(&hm, &diagonal).sizes, vec![35]);
+
+        let tall = input_parser("10 12 30\n11 40 25\n50 13 14");
+        let terrain = Terrain {
+            connectivity: Connectivity::Four,
+            wall: 30,
+        };
+        assert_eq!(terrain.low_points(&tall), vec![((0, 0), 11), ((2, 1), 14)]);
+        assert_eq!(BasinMap::with_terrain(&tall, &terrain).sizes, vec![3, 3]);
+        assert_eq!(separated_parser("10 12 30\n11 40 25\n50 13 14"), tall);
+
+        // A single column only has whitespace between rows
+        let column = separated_parser("10\n12\n30");
+        assert_eq!(column.len(), 3);
+        assert_eq!(column[&(1, 0)], 12);
+        // Leading or trailing whitespace doesn't make a grid separated
+        let digits = input_parser("2199943210 \n3987894921\n  9856789892");
+        assert_eq!(digits.len(), 30);
+        assert_eq!(digits[&(2, 0)], 9);
+        // Once a row is separated, all are
+        assert_eq!(input_parser("10 12\n30\n40 41").get(&(1, 0)), Some(&30));
+    }
 }
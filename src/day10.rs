@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use aoc_runner_derive::aoc;
 
 type Stack = Vec<char>;
@@ -17,7 +19,7 @@ fn match_parenthesis(s: &str) -> Result<Stack, char> {
                     _ => return Err(c),
                 }
             }
-            _ => unreachable!("No other chars than ()[]{}<>"),
+            _ => unreachable!("No other chars than ()[]{{}}<>"),
         }
     }
     Ok(stack)
@@ -60,6 +62,437 @@ pub fn part2(input: &str) -> usize {
     scores[scores.len() / 2]
 }
 
+/// Outcome of checking a line with a [`BracketChecker`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Check {
+    /// Every bracket is closed
+    Balanced,
+    /// Closing brackets completing the line
+    Incomplete { completion: String },
+    /// Wrong closer at byte `offset`, `expected` is `None` when nothing was open
+    Corrupted {
+        offset: usize,
+        found: char,
+        expected: Option<char>,
+    },
+    /// Character which is not a bracket at byte `offset`, when not skipping them
+    Unknown { offset: usize, found: char },
+}
+
+/// Bracket matching over a configurable table of pairs. Optionally skips any other
+/// character, quoted strings (with `\` escapes) and line comments.
+#[derive(Clone, Debug)]
+pub struct BracketChecker {
+    pairs: Vec<(char, char)>,
+    skip_unknown: bool,
+    quotes: Vec<char>,
+    line_comment: Option<String>,
+}
+
+impl Default for BracketChecker {
+    fn default() -> Self {
+        BracketChecker::new(&[('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')])
+    }
+}
+
+impl BracketChecker {
+    pub fn new(pairs: &[(char, char)]) -> Self {
+        BracketChecker {
+            pairs: pairs.to_vec(),
+            skip_unknown: false,
+            quotes: Vec::new(),
+            line_comment: None,
+        }
+    }
+
+    pub fn skip_unknown(mut self, skip: bool) -> Self {
+        self.skip_unknown = skip;
+        self
+    }
+
+    pub fn quotes(mut self, quotes: &[char]) -> Self {
+        self.quotes = quotes.to_vec();
+        self
+    }
+
+    pub fn line_comment(mut self, prefix: &str) -> Self {
+        self.line_comment = Some(prefix.to_string());
+        self
+    }
+
+    fn closer(&self, open: char) -> Option<char> {
+        self.pairs.iter().find(|(o, _)| *o == open).map(|(_, c)| *c)
+    }
+
+    fn is_closer(&self, c: char) -> bool {
+        self.pairs.iter().any(|(_, close)| *close == c)
+    }
+
+    /// Incremental checker fed with chunks of text
+    pub fn stream(&self) -> BracketStream<'_> {
+        BracketStream {
+            checker: self,
+            per_line: false,
+            offset: 0,
+            stack: Vec::new(),
+            corrupted: false,
+            started: false,
+            lexing: Lexing::Code,
+            pending: VecDeque::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    /// Check a whole line: the first error reported by the stream, otherwise what is left open
+    pub fn check(&self, s: &str) -> Check {
+        let mut stream = self.stream();
+        let mut events = stream.feed(s);
+        events.extend(stream.finish());
+        events
+            .into_iter()
+            .find_map(|event| match event {
+                Event::Mismatch {
+                    offset,
+                    found,
+                    expected,
+                } => Some(Check::Corrupted {
+                    offset,
+                    found,
+                    expected,
+                }),
+                Event::Unknown { offset, found } => Some(Check::Unknown { offset, found }),
+                Event::End {
+                    completion: Some(completion),
+                } if !completion.is_empty() => Some(Check::Incomplete { completion }),
+                _ => None,
+            })
+            .unwrap_or(Check::Balanced)
+    }
+}
+
+/// What a [`BracketStream`] saw, offsets are in bytes from the start of the stream
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event {
+    Opened {
+        offset: usize,
+        found: char,
+    },
+    Closed {
+        offset: usize,
+        found: char,
+    },
+    /// Wrong closer, `expected` is `None` when nothing was open
+    Mismatch {
+        offset: usize,
+        found: char,
+        expected: Option<char>,
+    },
+    /// Character which is not a bracket, when not skipping them
+    Unknown {
+        offset: usize,
+        found: char,
+    },
+    /// End of the stream (or of a line in per-line mode) with the closers still expected,
+    /// `None` once a mismatch occurred
+    End {
+        completion: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Lexing {
+    Code,
+    Quote(char),
+    Escape(char),
+    Comment,
+}
+
+/// Checks text chunk by chunk, keeping only the open brackets and the lexing state
+/// (inside a quote or a comment, or a partially seen comment prefix) between chunks.
+pub struct BracketStream<'a> {
+    checker: &'a BracketChecker,
+    per_line: bool,
+    offset: usize,
+    stack: Vec<char>,
+    corrupted: bool,
+    started: bool,
+    lexing: Lexing,
+    /// Characters possibly starting a comment prefix
+    pending: VecDeque<(usize, char)>,
+    /// Start of a UTF-8 sequence split across byte chunks
+    partial: Vec<u8>,
+}
+
+impl BracketStream<'_> {
+    /// Check every line independently, emitting an [`Event::End`] at each newline
+    pub fn per_line(mut self, per_line: bool) -> Self {
+        self.per_line = per_line;
+        self
+    }
+
+    pub fn feed(&mut self, chunk: &str) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.flush_partial(&mut events);
+        for (idx, c) in chunk.char_indices() {
+            self.push_char(self.offset + idx, c, &mut events);
+        }
+        self.offset += chunk.len();
+        events
+    }
+
+    /// Same as [`feed`](Self::feed) with raw bytes, which may split UTF-8 sequences:
+    /// a trailing partial sequence waits for the next chunk, and invalid bytes are
+    /// checked as U+FFFD.
+    pub fn feed_bytes(&mut self, chunk: &[u8]) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut bytes = std::mem::take(&mut self.partial);
+        bytes.extend_from_slice(chunk);
+        let mut rest = bytes.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    events.extend(self.feed(valid));
+                    return events;
+                }
+                Err(e) => {
+                    let (valid, invalid) = rest.split_at(e.valid_up_to());
+                    events.extend(self.feed(std::str::from_utf8(valid).unwrap()));
+                    match e.error_len() {
+                        Some(len) => {
+                            self.replace_invalid(len, &mut events);
+                            rest = &invalid[len..];
+                        }
+                        None => {
+                            self.partial = invalid.to_vec();
+                            return events;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.flush_partial(&mut events);
+        if self.started || !self.per_line {
+            self.end(&mut events);
+        }
+        events
+    }
+
+    fn push_char(&mut self, offset: usize, c: char, events: &mut Vec<Event>) {
+        if self.per_line && c == '\n' {
+            self.end(events);
+        } else {
+            self.started = true;
+            self.lex(offset, c, events);
+        }
+    }
+
+    /// Check `len` invalid bytes as a single U+FFFD
+    fn replace_invalid(&mut self, len: usize, events: &mut Vec<Event>) {
+        self.push_char(self.offset, char::REPLACEMENT_CHARACTER, events);
+        self.offset += len;
+    }
+
+    /// A sequence left incomplete by the last bytes is invalid
+    fn flush_partial(&mut self, events: &mut Vec<Event>) {
+        if !self.partial.is_empty() {
+            let len = std::mem::take(&mut self.partial).len();
+            self.replace_invalid(len, events);
+        }
+    }
+
+    fn end(&mut self, events: &mut Vec<Event>) {
+        self.flush_pending(events);
+        let completion = match self.corrupted {
+            true => None,
+            false => Some(self.stack.iter().rev().collect()),
+        };
+        events.push(Event::End { completion });
+        self.stack.clear();
+        self.corrupted = false;
+        self.started = false;
+        self.lexing = Lexing::Code;
+    }
+
+    fn flush_pending(&mut self, events: &mut Vec<Event>) {
+        while let Some((offset, c)) = self.pending.pop_front() {
+            self.code(offset, c, events);
+            if self.lexing != Lexing::Code {
+                // e.g. a quote opened, the rest is lexed from that state
+                let rest = self.pending.drain(..).collect::<Vec<_>>();
+                rest.into_iter()
+                    .for_each(|(offset, c)| self.lex(offset, c, events));
+            }
+        }
+    }
+
+    fn lex(&mut self, offset: usize, c: char, events: &mut Vec<Event>) {
+        match self.lexing {
+            Lexing::Quote(q) if c == '\\' => self.lexing = Lexing::Escape(q),
+            Lexing::Quote(q) if c == q => self.lexing = Lexing::Code,
+            Lexing::Escape(q) => self.lexing = Lexing::Quote(q),
+            Lexing::Comment if c == '\n' => self.lexing = Lexing::Code,
+            Lexing::Quote(_) | Lexing::Comment => {}
+            Lexing::Code => match &self.checker.line_comment {
+                Some(prefix) => {
+                    self.pending.push_back((offset, c));
+                    loop {
+                        let seen = self.pending.iter().map(|(_, c)| c).collect::<String>();
+                        if seen == *prefix {
+                            self.pending.clear();
+                            self.lexing = Lexing::Comment;
+                            return;
+                        }
+                        if prefix.starts_with(&seen) {
+                            return;
+                        }
+                        // Not a comment: the first character is code, retry from the next one
+                        let (offset, c) = self.pending.pop_front().unwrap();
+                        self.code(offset, c, events);
+                        if self.lexing != Lexing::Code {
+                            self.flush_pending(events);
+                            return;
+                        }
+                    }
+                }
+                None => self.code(offset, c, events),
+            },
+        }
+    }
+
+    fn code(&mut self, offset: usize, c: char, events: &mut Vec<Event>) {
+        if self.lexing != Lexing::Code {
+            return self.lex(offset, c, events);
+        }
+        if let Some(close) = self.checker.closer(c) {
+            self.stack.push(close);
+            events.push(Event::Opened { offset, found: c });
+        } else if self.checker.is_closer(c) {
+            let expected = self.stack.pop();
+            if expected == Some(c) {
+                events.push(Event::Closed { offset, found: c });
+            } else {
+                self.corrupted = true;
+                events.push(Event::Mismatch {
+                    offset,
+                    found: c,
+                    expected,
+                });
+            }
+        } else if self.checker.quotes.contains(&c) {
+            self.lexing = Lexing::Quote(c);
+        } else if !self.checker.skip_unknown {
+            events.push(Event::Unknown { offset, found: c });
+        }
+    }
+}
+
+/// Feed the input in small chunks to a per-line stream, as if it came from a socket
+fn stream_events(input: &str) -> Vec<Event> {
+    let checker = BracketChecker::default();
+    let mut stream = checker.stream().per_line(true);
+    let mut events = input
+        .as_bytes()
+        .chunks(64)
+        .flat_map(|chunk| stream.feed_bytes(chunk))
+        .collect::<Vec<_>>();
+    events.extend(stream.finish());
+    events
+}
+
+#[aoc(day10, part1, stream)]
+pub fn part1_stream(input: &str) -> usize {
+    let mut corrupted = false;
+    stream_events(input)
+        .into_iter()
+        .filter_map(|event| match event {
+            // Only the first mismatch of a line counts
+            Event::Mismatch { found, .. } if !corrupted => {
+                corrupted = true;
+                Some(found)
+            }
+            Event::End { .. } => {
+                corrupted = false;
+                None
+            }
+            _ => None,
+        })
+        .map(|c| match c {
+            ')' => 3,
+            ']' => 57,
+            '}' => 1197,
+            '>' => 25137,
+            _ => panic!("Only closing brackets should be illegal"),
+        })
+        .sum()
+}
+
+#[aoc(day10, part2, stream)]
+pub fn part2_stream(input: &str) -> usize {
+    let mut scores = stream_events(input)
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::End {
+                completion: Some(completion),
+            } if !completion.is_empty() => Some(completion),
+            _ => None,
+        })
+        .map(|completion| completion_score(&completion))
+        .collect::<Vec<_>>();
+    scores.sort_unstable();
+    scores[scores.len() / 2]
+}
+
+fn completion_score(completion: &str) -> usize {
+    completion.chars().fold(0, |score, c| {
+        score * 5
+            + match c {
+                ')' => 1,
+                ']' => 2,
+                '}' => 3,
+                '>' => 4,
+                _ => panic!("Only closing brackets complete a line!"),
+            }
+    })
+}
+
+#[aoc(day10, part1, checker)]
+pub fn part1_checker(input: &str) -> usize {
+    let checker = BracketChecker::default();
+    input
+        .lines()
+        .filter_map(|s| match checker.check(s) {
+            Check::Corrupted { found, .. } => Some(found),
+            _ => None,
+        })
+        .map(|c| match c {
+            ')' => 3,
+            ']' => 57,
+            '}' => 1197,
+            '>' => 25137,
+            _ => panic!("Only closing brackets should be illegal"),
+        })
+        .sum()
+}
+
+#[aoc(day10, part2, checker)]
+pub fn part2_checker(input: &str) -> usize {
+    let checker = BracketChecker::default();
+    let mut scores = input
+        .lines()
+        .filter_map(|s| match checker.check(s) {
+            Check::Incomplete { completion } => Some(completion),
+            _ => None,
+        })
+        .map(|completion| completion_score(&completion))
+        .collect::<Vec<_>>();
+    scores.sort_unstable();
+    scores[scores.len() / 2]
+}
+
 #[cfg(test)]
 mod test_day10 {
     use super::*;
@@ -85,4 +518,132 @@ mod test_day10 {
     fn test_part2() {
         assert_eq!(part2(TESTCASE), 288957)
     }
+
+    #[test]
+    fn test_stream() {
+        assert_eq!(part1_stream(TESTCASE), 26397);
+        assert_eq!(part2_stream(TESTCASE), 288957);
+
+        let checker = BracketChecker::new(&[('(', ')'), ('{', '}')])
+            .quotes(&['"'])
+            .line_comment("//");
+        let mut stream = checker.stream();
+        // Comment prefix and quote split across chunks
+        let mut events = ["{/", "/ ", "(\n", "\"", "\\", "\"}", "\"", ")/", "x"]
+            .iter()
+            .flat_map(|chunk| stream.feed(chunk))
+            .collect::<Vec<_>>();
+        events.extend(stream.finish());
+        assert_eq!(
+            events,
+            vec![
+                Event::Opened {
+                    offset: 0,
+                    found: '{'
+                },
+                Event::Mismatch {
+                    offset: 11,
+                    found: ')',
+                    expected: Some('}')
+                },
+                Event::Unknown {
+                    offset: 12,
+                    found: '/'
+                },
+                Event::Unknown {
+                    offset: 13,
+                    found: 'x'
+                },
+                Event::End { completion: None },
+            ]
+        );
+
+        // UTF-8 sequences split across byte chunks, invalid and truncated ones
+        let checker = BracketChecker::default();
+        let mut stream = checker.stream();
+        let bytes = "(é)".as_bytes();
+        let mut events = [&bytes[..2], &bytes[2..], b"\xff[", b"\xe2\x82"]
+            .iter()
+            .flat_map(|chunk| stream.feed_bytes(chunk))
+            .collect::<Vec<_>>();
+        events.extend(stream.finish());
+        assert_eq!(
+            events,
+            vec![
+                Event::Opened {
+                    offset: 0,
+                    found: '('
+                },
+                Event::Unknown {
+                    offset: 1,
+                    found: 'é'
+                },
+                Event::Closed {
+                    offset: 3,
+                    found: ')'
+                },
+                Event::Unknown {
+                    offset: 4,
+                    found: char::REPLACEMENT_CHARACTER
+                },
+                Event::Opened {
+                    offset: 5,
+                    found: '['
+                },
+                Event::Unknown {
+                    offset: 6,
+                    found: char::REPLACEMENT_CHARACTER
+                },
+                Event::End {
+                    completion: Some("]".to_string())
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_checker() {
+        assert_eq!(part1_checker(TESTCASE), 26397);
+        assert_eq!(part2_checker(TESTCASE), 288957);
+
+        let checker = BracketChecker::default();
+        assert_eq!(
+            checker.check("{([(<{}[<>[]}>{[]{[(<()>"),
+            Check::Corrupted {
+                offset: 12,
+                found: '}',
+                expected: Some(']')
+            }
+        );
+        assert_eq!(
+            checker.check("[({(<(())[]>[[{[]{<()<>>"),
+            Check::Incomplete {
+                completion: "}}]])})]".to_string()
+            }
+        );
+        assert_eq!(
+            checker.check("(a)"),
+            Check::Unknown {
+                offset: 1,
+                found: 'a'
+            }
+        );
+
+        let source = BracketChecker::new(&[('(', ')'), ('{', '}')])
+            .skip_unknown(true)
+            .quotes(&['"'])
+            .line_comment("//");
+        assert_eq!(
+            source.check("fn f() { g(\")\\\"\"); // }\n}"),
+            Check::Balanced
+        );
+        assert_eq!(
+            source.check("f(x))"),
+            Check::Corrupted {
+                offset: 4,
+                found: ')',
+                expected: None
+            }
+        );
+    }
 }
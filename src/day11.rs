@@ -2,21 +2,29 @@ use std::collections::VecDeque;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-const OCTO_WIDTH: usize = 10;
-const OCTOPUSES_COUNT: usize = OCTO_WIDTH * OCTO_WIDTH;
-const RANGE: core::ops::Range<i32> = 0..OCTO_WIDTH as i32;
+use crate::utils::{detect_cycle, Cycle};
 
-type Octopuses = [u32; OCTOPUSES_COUNT];
+/// Energy levels of a rectangular grid of octopuses (row-major)
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Octopuses {
+    energy: Vec<u32>,
+    width: usize,
+    height: usize,
+}
 
 #[aoc_generator(day11)]
 pub fn input_parser(input: &str) -> Octopuses {
-    let mut octos = [0; OCTOPUSES_COUNT];
-    input
-        .chars()
-        .filter(|c| *c != '\n')
-        .enumerate()
-        .for_each(|(idx, c)| octos[idx] = c.to_digit(10).unwrap());
-    octos
+    let width = input.lines().next().map_or(0, |l| l.len());
+    let energy = input
+        .lines()
+        .inspect(|l| assert_eq!(l.len(), width, "Grid should be rectangular"))
+        .flat_map(|l| l.chars().map(|c| c.to_digit(10).unwrap()))
+        .collect::<Vec<_>>();
+    Octopuses {
+        height: energy.len() / width.max(1),
+        energy,
+        width,
+    }
 }
 
 const NEIGHBORS: [(i32, i32); 8] = [
@@ -30,36 +38,40 @@ const NEIGHBORS: [(i32, i32); 8] = [
     (1, 1),
 ];
 
-fn octo_step(octopuses: &mut Octopuses, mut flashes: usize) -> usize {
-    octopuses.iter_mut().for_each(|e| *e += 1);
-    let mut flashing = octopuses
+/// Run one step and return the positions which flashed, in flashing order
+fn octo_step(octopuses: &mut Octopuses) -> Vec<usize> {
+    let (width, height) = (octopuses.width as i32, octopuses.height as i32);
+    let energy = &mut octopuses.energy;
+    energy.iter_mut().for_each(|e| *e += 1);
+    let mut flashing = energy
         .iter()
         .enumerate()
         .filter(|(_, e)| **e > 9)
         .map(|(pos, _)| pos)
         .collect::<VecDeque<_>>();
 
-    let mut visited = [false; OCTOPUSES_COUNT];
+    let mut flashed = Vec::new();
+    let mut visited = vec![false; energy.len()];
     while let Some(pos) = flashing.pop_front() {
         if !visited[pos] {
             visited[pos] = true;
-            // Count flash
-            flashes += 1;
+            // Record flash
+            flashed.push(pos);
             // Reset octopus
-            octopuses[pos] = 0;
+            energy[pos] = 0;
             //
-            let x = (pos / OCTO_WIDTH) as i32;
-            let y = pos.rem_euclid(OCTO_WIDTH) as i32;
+            let x = (pos / width as usize) as i32;
+            let y = pos.rem_euclid(width as usize) as i32;
             // Update neighbors
             NEIGHBORS.iter().for_each(|(dx, dy)| {
                 let (nx, ny) = (x + dx, y + dy);
-                if !RANGE.contains(&nx) || !RANGE.contains(&ny) {
+                if !(0..height).contains(&nx) || !(0..width).contains(&ny) {
                     return;
                 }
 
-                let neighbor = (nx * OCTO_WIDTH as i32 + ny) as usize;
+                let neighbor = (nx * width + ny) as usize;
                 if visited.get(neighbor) == Some(&false) {
-                    if let Some(e) = octopuses.get_mut(neighbor) {
+                    if let Some(e) = energy.get_mut(neighbor) {
                         *e += 1;
                         if *e > 9 {
                             flashing.push_back(neighbor)
@@ -69,19 +81,59 @@ fn octo_step(octopuses: &mut Octopuses, mut flashes: usize) -> usize {
             })
         }
     }
-    flashes
+    flashed
 }
 
 #[aoc(day11, part1)]
 pub fn part1(octopuses: &Octopuses) -> usize {
-    let mut octopuses = *octopuses;
-    (0..100).fold(0, |flashes, _| octo_step(&mut octopuses, flashes))
+    let mut octopuses = octopuses.clone();
+    (0..100).map(|_| octo_step(&mut octopuses).len()).sum()
 }
 
 #[aoc(day11, part2)]
 pub fn part2(octopuses: &Octopuses) -> Option<usize> {
-    let mut octopuses = *octopuses;
-    (1..).find(|_| octo_step(&mut octopuses, 0) == OCTOPUSES_COUNT)
+    let mut octopuses = octopuses.clone();
+    let count = octopuses.energy.len();
+    (1..).find(|_| octo_step(&mut octopuses).len() == count)
+}
+
+/// Positions (row-major) flashing at each step, step 1 first
+pub fn flash_history(octopuses: &Octopuses) -> impl Iterator<Item = Vec<usize>> {
+    let mut octopuses = octopuses.clone();
+    std::iter::repeat_with(move || octo_step(&mut octopuses))
+}
+
+/// First step where every octopus flashes, and the number of steps between
+/// the following synchronous flashes
+#[derive(Debug, PartialEq, Eq)]
+pub struct Synchronisation {
+    pub first: usize,
+    pub period: usize,
+}
+
+/// Look for a synchronisation within `max_steps`, then for the next one to measure the period
+pub fn synchronisation(octopuses: &Octopuses, max_steps: usize) -> Option<Synchronisation> {
+    let mut octopuses = octopuses.clone();
+    let count = octopuses.energy.len();
+    let mut synchronous = (1..=max_steps).filter(|_| octo_step(&mut octopuses).len() == count);
+    let first = synchronous.next()?;
+    let second = synchronous.next()?;
+    Some(Synchronisation {
+        first,
+        period: second - first,
+    })
+}
+
+/// Next state of the grid, for cycle detection
+pub fn next_octopuses(octopuses: &Octopuses) -> Octopuses {
+    let mut octopuses = octopuses.clone();
+    octo_step(&mut octopuses);
+    octopuses
+}
+
+/// Pre-period and period of the grid states, within about `max_steps`
+pub fn octopus_cycle(octopuses: &Octopuses, max_steps: usize) -> Option<Cycle<Octopuses>> {
+    detect_cycle(octopuses.clone(), next_octopuses, max_steps)
 }
 
 #[cfg(test)]
@@ -109,4 +161,51 @@ mod test_day11 {
     fn test_part2() {
         assert_eq!(part2(&input_parser(TESTCASE)), Some(195))
     }
+
+    #[test]
+    fn test_rectangular() {
+        let mut octopuses = input_parser("11111\n19991\n19191\n19991\n11111");
+        assert_eq!(octo_step(&mut octopuses).len(), 9);
+        assert_eq!(octopuses.energy[..5], [3, 4, 5, 4, 3]);
+
+        // Flashes are the same on the transposed grid
+        let rows = TESTCASE.lines().take(3).collect::<Vec<_>>();
+        let wide = input_parser(&rows.join("\n"));
+        let tall = input_parser(
+            &(0..10)
+                .map(|y| rows.iter().map(|r| &r[y..=y]).collect::<String>())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        assert_eq!((wide.width, wide.height), (10, 3));
+        assert_eq!((tall.width, tall.height), (3, 10));
+        assert_eq!(part1(&wide), part1(&tall));
+
+        let history = flash_history(&input_parser(TESTCASE)).take(10);
+        assert_eq!(history.map(|f| f.len()).sum::<usize>(), 204);
+
+        assert_eq!(
+            synchronisation(&input_parser(TESTCASE), 1000),
+            Some(Synchronisation {
+                first: 195,
+                period: 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_cycle() {
+        let octopuses = input_parser(TESTCASE);
+        let cycle = octopus_cycle(&octopuses, 1000).unwrap();
+        // The grid enters its cycle with the first synchronous flash
+        assert_eq!((cycle.start, cycle.period), (195, 10));
+
+        let all_zero = cycle.state_at(195 + 10 * 1_000_000_000, next_octopuses);
+        assert!(all_zero.energy.iter().all(|e| *e == 0));
+        let mut expected = octopuses.clone();
+        (0..100).for_each(|_| {
+            octo_step(&mut expected);
+        });
+        assert_eq!(cycle.state_at(100, next_octopuses), expected);
+    }
 }
@@ -6,9 +6,39 @@ pub struct Cave {
     graph: Vec<Vec<usize>>,
     small_caves: BitFlags,
     end: usize,
+    names: Vec<String>,
 }
 
-type BitFlags = u64;
+/// Set of caves: a single word up to 64 caves, growing beyond
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BitFlags {
+    Word(u64),
+    Words(Vec<u64>),
+}
+
+impl BitFlags {
+    fn new(len: usize) -> Self {
+        if len <= 64 {
+            BitFlags::Word(0)
+        } else {
+            BitFlags::Words(vec![0; len.div_ceil(64)])
+        }
+    }
+
+    fn contains(&self, idx: usize) -> bool {
+        match self {
+            BitFlags::Word(w) => w & 1 << idx != 0,
+            BitFlags::Words(ws) => ws[idx / 64] & 1 << (idx % 64) != 0,
+        }
+    }
+
+    fn insert(&mut self, idx: usize) {
+        match self {
+            BitFlags::Word(w) => *w |= 1 << idx,
+            BitFlags::Words(ws) => ws[idx / 64] |= 1 << (idx % 64),
+        }
+    }
+}
 
 #[aoc_generator(day12)]
 pub fn input_parser(input: &str) -> Cave {
@@ -25,11 +55,12 @@ pub fn input_parser(input: &str) -> Cave {
     let start = 0;
     let end = nodes.len() - 1;
 
-    let small_caves = nodes
+    let mut small_caves = BitFlags::new(nodes.len());
+    nodes
         .iter()
         .enumerate()
-        .map(|(idx, n)| ((*n == n.to_lowercase()) as u64) << idx)
-        .sum();
+        .filter(|(_, n)| **n == n.to_lowercase())
+        .for_each(|(idx, _)| small_caves.insert(idx));
 
     let mut graph = vec![Vec::new(); nodes.len()];
 
@@ -50,6 +81,7 @@ pub fn input_parser(input: &str) -> Cave {
         graph,
         small_caves,
         end,
+        names: nodes.iter().map(|n| n.to_string()).collect(),
     }
 }
 
@@ -60,32 +92,29 @@ fn dfs(
     mut seen_twice: bool,
     memo: &mut HashMap<(bool, BitFlags, usize), usize>,
 ) -> usize {
-
     if node == cave.end {
         return 1;
     }
 
-    let key = (seen_twice, seen, node);
+    let key = (seen_twice, seen.clone(), node);
     if let Some(paths) = memo.get(&key) {
         return *paths;
     }
 
-    let mask = 1 << node;
-
-    if cave.small_caves & mask != 0 {
-        if seen & mask != 0 {
+    if cave.small_caves.contains(node) {
+        if seen.contains(node) {
             seen_twice = true
         }
-        seen |= mask;
+        seen.insert(node);
     }
 
     let mut paths = 0;
     for &neighbor in cave.graph[node].iter() {
-        if seen & (1 << neighbor) != 0 && seen_twice {
+        if seen.contains(neighbor) && seen_twice {
             continue;
         }
 
-        paths += dfs(neighbor, cave, seen, seen_twice, memo)
+        paths += dfs(neighbor, cave, seen.clone(), seen_twice, memo)
     }
 
     memo.insert(key, paths);
@@ -95,13 +124,299 @@ fn dfs(
 #[aoc(day12, part1)]
 pub fn part1(cave: &Cave) -> usize {
     let memo = &mut HashMap::new();
-    dfs(0, cave, 0, true, memo)
+    dfs(0, cave, BitFlags::new(cave.names.len()), true, memo)
 }
 
 #[aoc(day12, part2)]
 pub fn part2(cave: &Cave) -> usize {
     let memo = &mut HashMap::new();
-    dfs(0, cave, 0, false, memo)
+    dfs(0, cave, BitFlags::new(cave.names.len()), false, memo)
+}
+
+/// Generalised revisits of small caves
+#[derive(Clone, Debug, Default)]
+pub struct VisitRules {
+    /// Visits of already visited small caves allowed in total (0 in part1, 1 in part2)
+    pub budget: u32,
+    /// Maximum number of visits of some small caves, otherwise only bounded by the budget
+    pub limits: HashMap<String, u32>,
+}
+
+type RulesMemo = HashMap<(usize, BitFlags, Vec<(usize, u32)>), usize>;
+
+/// Memoised on the current cave, the small caves seen and those seen more than once
+/// (sorted, with their visit count) which also accounts for the budget spent.
+fn dfs_rules(
+    node: usize,
+    cave: &Cave,
+    seen: &BitFlags,
+    revisits: &[(usize, u32)],
+    budget: u32,
+    limits: &[u32],
+    memo: &mut RulesMemo,
+) -> usize {
+    if node == cave.end {
+        return 1;
+    }
+
+    let key = (node, seen.clone(), revisits.to_vec());
+    if let Some(paths) = memo.get(&key) {
+        return *paths;
+    }
+
+    let spent = revisits.iter().map(|(_, visits)| visits - 1).sum::<u32>();
+    let mut paths = 0;
+    for &neighbor in cave.graph[node].iter() {
+        if !cave.is_small(neighbor) {
+            paths += dfs_rules(neighbor, cave, seen, revisits, budget, limits, memo);
+        } else if !seen.contains(neighbor) {
+            if limits[neighbor] == 0 {
+                continue;
+            }
+            let mut seen = seen.clone();
+            seen.insert(neighbor);
+            paths += dfs_rules(neighbor, cave, &seen, revisits, budget, limits, memo);
+        } else {
+            let idx = revisits.binary_search_by_key(&neighbor, |(n, _)| *n);
+            let visits = idx.map_or(1, |idx| revisits[idx].1);
+            if spent >= budget || visits >= limits[neighbor] {
+                continue;
+            }
+            let mut revisits = revisits.to_vec();
+            match idx {
+                Ok(idx) => revisits[idx].1 += 1,
+                Err(idx) => revisits.insert(idx, (neighbor, 2)),
+            }
+            paths += dfs_rules(neighbor, cave, seen, &revisits, budget, limits, memo);
+        }
+    }
+
+    memo.insert(key, paths);
+    paths
+}
+
+/// Number of paths when small caves can be revisited according to `rules`
+pub fn count_paths(cave: &Cave, rules: &VisitRules) -> usize {
+    let limits = cave
+        .names
+        .iter()
+        .map(|n| *rules.limits.get(n).unwrap_or(&u32::MAX))
+        .collect::<Vec<_>>();
+    let mut seen = BitFlags::new(cave.names.len());
+    seen.insert(0);
+    let memo = &mut HashMap::new();
+    dfs_rules(0, cave, &seen, &[], rules.budget, &limits, memo)
+}
+
+/// Structural issues of a cave graph, to check before counting paths
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CaveReport {
+    /// Adjacent big caves: paths could bounce between them forever
+    pub big_loops: Vec<(String, String)>,
+    /// Caves which can't be reached from start
+    pub unreachable: Vec<String>,
+    /// Caves from which end can't be reached
+    pub dead_ends: Vec<String>,
+}
+
+impl CaveReport {
+    /// Whether `dfs` terminates on this graph
+    pub fn is_finite(&self) -> bool {
+        self.big_loops.is_empty()
+    }
+}
+
+impl Cave {
+    /// Undirected edges, as parsed
+    fn edges(&self) -> Vec<(usize, usize)> {
+        self.graph
+            .iter()
+            .enumerate()
+            .flat_map(|(a, neighbors)| neighbors.iter().map(move |b| (a.min(*b), a.max(*b))))
+            .unique()
+            .collect()
+    }
+
+    /// Caves reachable from `from` following the graph, or against it when `reverse`
+    fn reachable(&self, from: usize, reverse: bool) -> BitFlags {
+        let mut seen = BitFlags::new(self.names.len());
+        seen.insert(from);
+        let mut stack = vec![from];
+        while let Some(node) = stack.pop() {
+            let next = (0..self.names.len()).filter(|n| match reverse {
+                false => self.graph[node].contains(n),
+                true => self.graph[*n].contains(&node),
+            });
+            for n in next.collect::<Vec<_>>() {
+                if !seen.contains(n) {
+                    seen.insert(n);
+                    stack.push(n);
+                }
+            }
+        }
+        seen
+    }
+
+    pub fn report(&self) -> CaveReport {
+        let name = |n: usize| self.names[n].clone();
+        let big_loops = self
+            .edges()
+            .into_iter()
+            .filter(|(a, b)| !self.is_small(*a) && !self.is_small(*b))
+            .map(|(a, b)| (name(a), name(b)))
+            .collect();
+        let from_start = self.reachable(0, false);
+        let to_end = self.reachable(self.end, true);
+        CaveReport {
+            big_loops,
+            unreachable: (0..self.names.len())
+                .filter(|n| !from_start.contains(*n))
+                .map(name)
+                .collect(),
+            dead_ends: (0..self.names.len())
+                .filter(|n| !to_end.contains(*n))
+                .map(name)
+                .collect(),
+        }
+    }
+
+    /// Graphviz DOT rendering: big caves as filled boxes, small caves as ellipses,
+    /// start and end highlighted
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("graph caves {\n");
+        for (idx, name) in self.names.iter().enumerate() {
+            let mut style = match self.is_small(idx) {
+                true => "shape=ellipse".to_string(),
+                false => "shape=box, style=filled, fillcolor=lightgrey".to_string(),
+            };
+            if idx == 0 {
+                style.push_str(", color=green, penwidth=3");
+            } else if idx == self.end {
+                style.push_str(", color=red, penwidth=3");
+            }
+            dot.push_str(&format!("    \"{}\" [{}];\n", name, style));
+        }
+        for (a, b) in self.edges() {
+            dot.push_str(&format!(
+                "    \"{}\" -- \"{}\";\n",
+                self.names[a], self.names[b]
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Which paths to enumerate with [`Cave::paths`]
+#[derive(Clone, Debug, Default)]
+pub struct PathQuery {
+    /// Allow a single small cave to be visited twice (part2)
+    pub twice: bool,
+    /// Maximum number of caves in a path, start and end included
+    pub max_len: Option<usize>,
+    /// Caves every path has to go through
+    pub must_visit: Vec<String>,
+}
+
+impl Cave {
+    /// Lazily enumerate the paths from start to end as cave names
+    pub fn paths(&self, query: &PathQuery) -> Paths<'_> {
+        let must_visit = query
+            .must_visit
+            .iter()
+            .map(|name| self.names.iter().position(|n| n == name))
+            .collect::<Option<Vec<_>>>();
+        let mut visits = vec![0; self.names.len()];
+        visits[0] = 1;
+        Paths {
+            cave: self,
+            twice: query.twice,
+            max_len: query.max_len.unwrap_or(usize::MAX),
+            // An unknown cave can't be visited: no path at all
+            stack: if must_visit.is_some() {
+                vec![(0, 0)]
+            } else {
+                Vec::new()
+            },
+            must_visit: must_visit.unwrap_or_default(),
+            visits,
+            twice_used: false,
+        }
+    }
+
+    fn is_small(&self, node: usize) -> bool {
+        self.small_caves.contains(node)
+    }
+}
+
+/// Iterative DFS over the caves, the stack holds the current path and the next neighbor to try
+pub struct Paths<'a> {
+    cave: &'a Cave,
+    twice: bool,
+    max_len: usize,
+    must_visit: Vec<usize>,
+    stack: Vec<(usize, usize)>,
+    visits: Vec<u32>,
+    twice_used: bool,
+}
+
+impl Paths<'_> {
+    fn can_visit(&self, node: usize) -> bool {
+        !self.cave.is_small(node) || self.visits[node] == 0 || (self.twice && !self.twice_used)
+    }
+
+    fn push(&mut self, node: usize) {
+        if self.cave.is_small(node) && self.visits[node] == 1 {
+            self.twice_used = true;
+        }
+        self.visits[node] += 1;
+        self.stack.push((node, 0));
+    }
+
+    fn pop(&mut self) {
+        if let Some((node, _)) = self.stack.pop() {
+            self.visits[node] -= 1;
+            if self.cave.is_small(node) && self.visits[node] == 1 {
+                self.twice_used = false;
+            }
+        }
+    }
+}
+
+impl Iterator for Paths<'_> {
+    type Item = Vec<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, next) = self.stack.last_mut()?;
+            let neighbor = match self.cave.graph[*node].get(*next) {
+                Some(neighbor) => *neighbor,
+                None => {
+                    self.pop();
+                    continue;
+                }
+            };
+            *next += 1;
+
+            if !self.can_visit(neighbor) || self.stack.len() >= self.max_len {
+                continue;
+            }
+            self.push(neighbor);
+            if neighbor == self.cave.end {
+                let complete = self.must_visit.iter().all(|m| self.visits[*m] > 0);
+                let path = complete.then(|| {
+                    self.stack
+                        .iter()
+                        .map(|(n, _)| self.cave.names[*n].clone())
+                        .collect()
+                });
+                self.pop();
+                if path.is_some() {
+                    return path;
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -137,4 +452,77 @@ start-RW";
     fn test_part2() {
         assert_eq!(part2(&input_parser(TESTCASE)), 3509)
     }
+
+    #[test]
+    fn test_visit_rules() {
+        let cave = input_parser(TESTCASE);
+        let budget = |budget| VisitRules {
+            budget,
+            ..Default::default()
+        };
+        assert_eq!(count_paths(&cave, &budget(0)), 226);
+        assert_eq!(count_paths(&cave, &budget(1)), 3509);
+
+        // Forbidding revisits of every small cave brings part2 back to part1
+        let mut rules = budget(1);
+        for name in ["fs", "he", "pj", "sl", "zg"] {
+            rules.limits.insert(name.to_string(), 1);
+        }
+        assert_eq!(count_paths(&cave, &rules), 226);
+        assert!(count_paths(&cave, &budget(2)) > 3509);
+
+        // More than 64 caves, in a single corridor with a detour through a big cave
+        let mut input = (1..100)
+            .map(|i| format!("c{}-c{}", i - 1, i))
+            .collect::<Vec<_>>();
+        input.extend(["start-c0".to_string(), "c99-end".to_string()]);
+        input.extend(["c80-BIG".to_string(), "BIG-c90".to_string()]);
+        let corridor = input_parser(&input.join("\n"));
+        assert_eq!(part1(&corridor), 2);
+        assert_eq!(count_paths(&corridor, &budget(0)), 2);
+        assert_eq!(part2(&corridor), count_paths(&corridor, &budget(1)));
+    }
+
+    #[test]
+    fn test_report() {
+        let cave = input_parser(TESTCASE);
+        assert_eq!(cave.report(), CaveReport::default());
+
+        let dot = cave.to_dot();
+        assert!(dot.starts_with("graph caves {"));
+        assert!(dot.contains("\"start\" [shape=ellipse, color=green, penwidth=3];"));
+        assert!(dot.contains("\"DX\" [shape=box, style=filled, fillcolor=lightgrey];"));
+        assert_eq!(dot.matches(" -- ").count(), 18);
+
+        let broken = input_parser("start-A\nA-B\nB-end\nc-d\nstart-x");
+        let report = broken.report();
+        assert!(!report.is_finite());
+        assert_eq!(report.big_loops, vec![("A".to_string(), "B".to_string())]);
+        assert_eq!(report.unreachable, vec!["c", "d"]);
+        assert_eq!(report.dead_ends, vec!["c", "d", "x"]);
+    }
+
+    #[test]
+    fn test_paths() {
+        let cave = input_parser(TESTCASE);
+        assert_eq!(cave.paths(&PathQuery::default()).count(), 226);
+        let twice = PathQuery {
+            twice: true,
+            ..Default::default()
+        };
+        assert_eq!(cave.paths(&twice).count(), 3509);
+
+        let small = input_parser("start-A\nstart-b\nA-c\nA-b\nb-d\nA-end\nb-end");
+        let query = PathQuery {
+            max_len: Some(4),
+            must_visit: vec!["b".to_string()],
+            ..Default::default()
+        };
+        let paths = small
+            .paths(&query)
+            .map(|p| p.join(","))
+            .sorted()
+            .collect_vec();
+        assert_eq!(paths, vec!["start,A,b,end", "start,b,A,end", "start,b,end"]);
+    }
 }
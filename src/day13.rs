@@ -1,13 +1,27 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use hashbrown::HashSet;
+use hashbrown::{HashMap, HashSet};
 use itertools::Itertools;
 use std::cmp::Ordering;
+
+use crate::utils::{ocr, write_pnm, PnmFormat};
+
 pub enum Fold {
-    X(u32),
-    Y(u32),
+    X(i32),
+    Y(i32),
+    /// Along y=x, bringing the part below the diagonal (y > x) over the part above
+    Diagonal,
 }
 
-type Dots = HashSet<(u32, u32)>;
+/// Coordinates are signed: folding along a line left of the middle of the paper
+/// brings dots before 0, see [`normalise`].
+type Dots = HashSet<(i32, i32)>;
+
+/// Dots moved so that their bounding box starts at (0, 0)
+pub fn normalise(dots: &Dots) -> Dots {
+    let xmin = dots.iter().map(|(x, _)| *x).min().unwrap_or(0);
+    let ymin = dots.iter().map(|(_, y)| *y).min().unwrap_or(0);
+    dots.iter().map(|(x, y)| (x - xmin, y - ymin)).collect()
+}
 
 pub struct Input {
     dots: Dots,
@@ -28,35 +42,78 @@ pub fn input_parser(input: &str) -> Input {
         .lines()
         .map(|s| {
             let (axis, val) = s["fold along ".len()..].split_once('=').unwrap();
-            let val: u32 = val.parse().unwrap();
-            match axis {
-                "x" => Fold::X(val),
-                "y" => Fold::Y(val),
-                _ => panic!("Only x & y"),
+            match (axis, val) {
+                ("y", "x") => Fold::Diagonal,
+                ("x", val) => Fold::X(val.parse().unwrap()),
+                ("y", val) => Fold::Y(val.parse().unwrap()),
+                _ => panic!("Only x, y & y=x"),
             }
         })
         .collect();
     Input { dots, folds }
 }
 
-fn fold_paper(dots: Dots, fold: &Fold) -> Dots {
+/// Where a dot lands after a fold, `None` if it lies on the fold line
+fn fold_point((x, y): (i32, i32), fold: &Fold) -> Option<(i32, i32)> {
     match fold {
-        Fold::X(folding_axis) => dots
-            .into_iter()
-            .filter_map(|(x, y)| match x.cmp(folding_axis) {
-                Ordering::Less => Some((x, y)),
-                Ordering::Equal => None,
-                Ordering::Greater => Some((2 * folding_axis - x, y)),
-            })
-            .collect(),
-        Fold::Y(folding_axis) => dots
-            .into_iter()
-            .filter_map(|(x, y)| match y.cmp(folding_axis) {
-                Ordering::Less => Some((x, y)),
-                Ordering::Equal => None,
-                Ordering::Greater => Some((x, 2 * folding_axis - y)),
-            })
-            .collect(),
+        Fold::X(folding_axis) => match x.cmp(folding_axis) {
+            Ordering::Less => Some((x, y)),
+            Ordering::Equal => None,
+            Ordering::Greater => Some((2 * folding_axis - x, y)),
+        },
+        Fold::Y(folding_axis) => match y.cmp(folding_axis) {
+            Ordering::Less => Some((x, y)),
+            Ordering::Equal => None,
+            Ordering::Greater => Some((x, 2 * folding_axis - y)),
+        },
+        Fold::Diagonal => match y.cmp(&x) {
+            Ordering::Less => Some((x, y)),
+            Ordering::Equal => None,
+            Ordering::Greater => Some((y, x)),
+        },
+    }
+}
+
+fn fold_paper(dots: Dots, fold: &Fold) -> Dots {
+    dots.into_iter()
+        .filter_map(|dot| fold_point(dot, fold))
+        .collect()
+}
+
+/// Paper after each fold, with the original dots ending up on each final dot
+pub struct FoldHistory {
+    /// `steps[0]` is the original paper, `steps[i]` the paper after `i` folds
+    pub steps: Vec<Dots>,
+    origins: HashMap<(i32, i32), Vec<(i32, i32)>>,
+}
+
+impl FoldHistory {
+    pub fn new(input: &Input) -> Self {
+        let mut steps = vec![input.dots.clone()];
+        for fold in input.folds.iter() {
+            steps.push(fold_paper(steps[steps.len() - 1].clone(), fold));
+        }
+
+        let mut origins = HashMap::<_, Vec<_>>::new();
+        for dot in input.dots.iter() {
+            let folded = input.folds.iter().try_fold(*dot, fold_point);
+            if let Some(folded) = folded {
+                origins.entry(folded).or_default().push(*dot);
+            }
+        }
+        origins.values_mut().for_each(|o| o.sort_unstable());
+
+        FoldHistory { steps, origins }
+    }
+
+    /// Original dots folded onto `dot` of the final paper
+    pub fn origins(&self, dot: (i32, i32)) -> &[(i32, i32)] {
+        self.origins.get(&dot).map_or(&[], |o| o.as_slice())
+    }
+
+    /// Paper before the last `n` folds
+    pub fn undo(&self, n: usize) -> Option<&Dots> {
+        self.steps.len().checked_sub(n + 1).map(|i| &self.steps[i])
     }
 }
 
@@ -65,34 +122,108 @@ pub fn part1(input: &Input) -> usize {
     fold_paper(input.dots.clone(), &input.folds[0]).len()
 }
 
+/// Dots rendered densely over their bounding box, or over a canvas anchored at its top left
+///
 /// Coordinates are unusual
 /// 0 -- x
 /// |
 /// y
-fn print_dots(dots: Dots) -> String {
-    let xmax = *dots.iter().map(|(x, _)| x).max().unwrap();
-    let ymax = *dots.iter().map(|(_, y)| y).max().unwrap();
-    (0..=ymax)
-        .map(|line| {
-            let row = dots
-                .iter()
-                .filter(|(_, y)| *y == line)
-                .map(|(x, _)| *x)
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    /// Canvas fitting the bounding box of the dots
+    pub fn new(dots: &Dots) -> Self {
+        let xmin = dots.iter().map(|(x, _)| *x).min().unwrap_or(0);
+        let xmax = dots.iter().map(|(x, _)| *x).max().unwrap_or(0);
+        let ymin = dots.iter().map(|(_, y)| *y).min().unwrap_or(0);
+        let ymax = dots.iter().map(|(_, y)| *y).max().unwrap_or(0);
+        let size = match dots.is_empty() {
+            true => (0, 0),
+            false => ((xmax - xmin + 1) as usize, (ymax - ymin + 1) as usize),
+        };
+        Bitmap::with_canvas(dots, size.0, size.1)
+    }
+
+    /// Canvas of the given size: dots beyond it are cropped, missing space left blank
+    pub fn with_canvas(dots: &Dots, width: usize, height: usize) -> Self {
+        let mut pixels = vec![false; width * height];
+        for (x, y) in normalise(dots) {
+            let (x, y) = (x as usize, y as usize);
+            if x < width && y < height {
+                pixels[y * width + x] = true;
+            }
+        }
+        Bitmap {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn rows(&self) -> Vec<Vec<bool>> {
+        self.pixels
+            .chunks(self.width.max(1))
+            .map(|r| r.to_vec())
+            .collect()
+    }
+
+    pub fn render(&self, on: char, off: char) -> String {
+        self.pixels
+            .chunks(self.width.max(1))
+            .map(|r| {
+                r.iter()
+                    .map(|p| if *p { on } else { off })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+
+    /// Binary PBM (P4), rows packed into bytes with 1 for a dot
+    pub fn write_pbm<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        write!(w, "P4\n{} {}\n", self.width, self.height)?;
+        for row in self.pixels.chunks(self.width.max(1)) {
+            let bytes = row
+                .chunks(8)
+                .map(|bits| {
+                    bits.iter()
+                        .enumerate()
+                        .fold(0u8, |byte, (i, b)| byte | (*b as u8) << (7 - i))
+                })
                 .collect::<Vec<_>>();
-            (0..=xmax)
-                .map(|x| if row.contains(&x) { '#' } else { ' ' })
-                .collect::<String>()
-        })
-        .join("\n")
+            w.write_all(&bytes)?;
+        }
+        Ok(())
+    }
 }
 
+fn print_dots(dots: &Dots) -> String {
+    Bitmap::new(dots).render('#', ' ')
+}
+
+fn fold_all(input: &Input) -> Dots {
+    input.folds.iter().fold(input.dots.clone(), fold_paper)
+}
+
+/// Letters read from the folded paper, or the paper itself if they can't be recognised
 #[aoc(day13, part2)]
 pub fn part2(input: &Input) -> String {
-    let dots = input
-        .folds
-        .iter()
-        .fold(input.dots.clone(), |dots, fold| fold_paper(dots, fold));
-    format!("\n{}", print_dots(dots))
+    let dots = fold_all(input);
+    ocr(&Bitmap::new(&dots).rows()).unwrap_or_else(|| format!("\n{}", print_dots(&dots)))
+}
+
+/// Export the fully folded paper as an image, one pixel per dot
+pub fn export_folded<W: std::io::Write>(
+    input: &Input,
+    w: &mut W,
+    format: PnmFormat,
+) -> std::io::Result<()> {
+    let bitmap = Bitmap::new(&fold_all(input));
+    let pixels = bitmap.pixels.iter().map(|p| *p as u32).collect::<Vec<_>>();
+    write_pnm(w, &pixels, bitmap.width, format)
 }
 
 #[cfg(test)]
@@ -126,4 +257,84 @@ fold along x=5";
     fn test_part1() {
         assert_eq!(part1(&input_parser(TESTCASE)), 17)
     }
+
+    #[test]
+    fn test_part2() {
+        // A square is no letter
+        assert_eq!(
+            part2(&input_parser(TESTCASE)),
+            "\n#####\n#   #\n#   #\n#   #\n#####"
+        );
+
+        let letters = "\
+#..#.####.#....#.....##.
+#..#.#....#....#....#..#
+####.###..#....#....#..#
+#..#.#....#....#....#..#
+#..#.#....#....#....#..#
+#..#.####.####.####..##.";
+        let dots = letters
+            .lines()
+            .enumerate()
+            .flat_map(|(y, l)| {
+                l.char_indices()
+                    .filter(|(_, c)| *c == '#')
+                    .map(move |(x, _)| (x as i32, y as i32))
+            })
+            .collect();
+        let input = Input {
+            dots,
+            folds: Vec::new(),
+        };
+        assert_eq!(part2(&input), "HELLO");
+    }
+
+    #[test]
+    fn test_bitmap() {
+        let dots = [(3, 2), (5, 2), (4, 4)].into_iter().collect();
+        let bitmap = Bitmap::new(&dots);
+        assert_eq!((bitmap.width, bitmap.height), (3, 3));
+        assert_eq!(bitmap.render('#', '.'), "#.#\n...\n.#.");
+        assert_eq!(
+            Bitmap::with_canvas(&dots, 2, 4).render('X', ' '),
+            "X \n  \n X\n  "
+        );
+
+        let mut pbm = Vec::new();
+        bitmap.write_pbm(&mut pbm).unwrap();
+        assert_eq!(pbm, b"P4\n3 3\n\xa0\x00\x40");
+
+        let mut pgm = Vec::new();
+        export_folded(&input_parser(TESTCASE), &mut pgm, PnmFormat::Pgm).unwrap();
+        assert!(pgm.starts_with(b"P5\n5 5\n255\n"));
+        assert_eq!(pgm.len(), "P5\n5 5\n255\n".len() + 25);
+    }
+
+    #[test]
+    fn test_folds() {
+        // Folding left of the middle brings the right part past the left edge
+        let input = input_parser("1,0\n2,0\n7,1\n\nfold along x=2\nfold along y=x");
+        let once = fold_paper(input.dots.clone(), &input.folds[0]);
+        assert_eq!(once, [(1, 0), (-3, 1)].into_iter().collect());
+        assert_eq!(normalise(&once), [(4, 0), (0, 1)].into_iter().collect());
+
+        let history = FoldHistory::new(&input);
+        assert_eq!(history.steps.len(), 3);
+        assert_eq!(history.steps[2], [(1, 0), (1, -3)].into_iter().collect());
+        assert_eq!(history.origins((1, -3)), [(7, 1)]);
+        assert_eq!(history.origins((2, 0)), []);
+        assert_eq!(history.undo(1), Some(&once));
+        assert_eq!(history.undo(2), Some(&input.dots));
+        assert_eq!(history.undo(3), None);
+
+        // Everything lands on the example's square
+        let input = input_parser(TESTCASE);
+        let history = FoldHistory::new(&input);
+        let origins = fold_all(&input)
+            .into_iter()
+            .map(|dot| history.origins(dot).len())
+            .sum::<usize>();
+        assert_eq!(origins, input.dots.len());
+        assert_eq!(history.origins((2, 4)), [(8, 4), (8, 10)]);
+    }
 }
@@ -1,12 +1,72 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use hashbrown::HashMap;
 use itertools::Itertools;
+use std::collections::BTreeMap;
+
+use crate::utils::{mat_pow_vec, mul_add, Matrix};
+
+/// Number of each element in a polymer
+pub type Histogram = BTreeMap<char, u64>;
+
+/// A polymer growing by pair insertion, tracked as pair counts.
+/// Pairs without a rule are left as they are.
+pub trait PolymerModel {
+    type Counts: Clone;
+
+    /// Pair counts of the template
+    fn initial_counts(&self) -> Self::Counts;
+
+    /// Pair counts after one more insertion step
+    fn step(&self, counts: &Self::Counts) -> Self::Counts;
+
+    /// Element histogram of the polymer described by `counts`
+    fn histogram_of(&self, counts: &Self::Counts) -> Histogram;
+
+    /// Element histogram after `steps`
+    fn histogram(&self, steps: usize) -> Histogram {
+        let counts = (0..steps).fold(self.initial_counts(), |c, _| self.step(&c));
+        self.histogram_of(&counts)
+    }
+
+    /// Element histograms step by step, template first
+    fn histograms(&self) -> Histograms<'_, Self>
+    where
+        Self: Sized,
+    {
+        Histograms {
+            model: self,
+            counts: self.initial_counts(),
+        }
+    }
+}
+
+pub struct Histograms<'a, M: PolymerModel> {
+    model: &'a M,
+    counts: M::Counts,
+}
+
+impl<M: PolymerModel> Iterator for Histograms<'_, M> {
+    type Item = Histogram;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let histogram = self.model.histogram_of(&self.counts);
+        self.counts = self.model.step(&self.counts);
+        Some(histogram)
+    }
+}
+
+/// Difference between the most and least common elements, 0 for an empty polymer
+pub fn spread(histogram: &Histogram) -> u64 {
+    let max = histogram.values().max().unwrap_or(&0);
+    let min = histogram.values().min().unwrap_or(&0);
+    max - min
+}
 
 pub struct InputVec {
     pairs: Vec<(char, char)>,
-    pair_count: Vec<usize>,
-    rules: Vec<(usize, usize)>,
-    last_element: char,
+    pair_count: Vec<u64>,
+    rules: Vec<Option<(usize, usize)>>,
+    last_element: Option<char>,
 }
 
 /// ABC => 1,12
@@ -15,31 +75,23 @@ pub struct InputVec {
 /// ..
 #[aoc_generator(day14, vec_of_int)]
 pub fn input_vec_parser(input: &str) -> InputVec {
-    let (polymer, rules) = input.split_once("\n\n").unwrap();
-
-    // Vec of pairs in rules (exhaustive)
-    // e.g. [(A, B), (A, C), (C, B), (B, C), ..]
-    let pairs = rules
-        .lines()
-        .map(|l| {
-            let (pair, _) = l.split_once(" -> ").unwrap();
-            let mut ch = pair.chars();
-            (ch.next().unwrap(), ch.next().unwrap())
+    let (polymer, rules) = input.split_once("\n\n").unwrap_or((input, ""));
+    let polymer = polymer.trim();
+
+    // Vec of pairs in rules and polymer, with their index in `pairs`
+    // e.g. [(A, B), (A, C), (C, B), (B, C), ..] and (A, B) => 0, (A, C) => 1, ..
+    let mut pairs = Vec::new();
+    let mut pairs_idx = HashMap::new();
+    let mut index = |pair: (char, char)| {
+        *pairs_idx.entry(pair).or_insert_with(|| {
+            pairs.push(pair);
+            pairs.len() - 1
         })
-        .collect::<Vec<_>>();
-
-    // Map pair to index in `pairs`
-    // e.g. (A, B) => 0, (A, C) => 1, ..
-    let pairs_idx = pairs
-        .clone()
-        .into_iter()
-        .enumerate()
-        .map(|(idx, pair)| (pair, idx))
-        .collect::<HashMap<_, _>>();
+    };
 
-    // Vec of rules (pair_idx => (pair1_idx, pair2_idx))
-    // e.g. AB => C becomes AB => AC, CB which becomes 0 => (1, 2)
-    let mut rules = rules
+    // Rules as (pair_idx, (pair1_idx, pair2_idx))
+    // e.g. AB => C becomes AB => AC, CB which becomes (0, (1, 2))
+    let rules = rules
         .lines()
         .map(|l| {
             let (pair, element) = l.split_once(" -> ").unwrap();
@@ -47,74 +99,89 @@ pub fn input_vec_parser(input: &str) -> InputVec {
             let (p1, p2) = (pair.next().unwrap(), pair.next().unwrap());
             let element = element.chars().next().unwrap();
             (
-                pairs_idx[&(p1, p2)],
-                (pairs_idx[&(p1, element)], pairs_idx[&(element, p2)]),
+                index((p1, p2)),
+                (index((p1, element)), index((element, p2))),
             )
         })
         .collect::<Vec<_>>();
 
-    // Sort by the index (same order as pair) and remove it
-    rules.sort_by_key(|(idx, _)| *idx);
-    let rules = rules.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
+    // Pairs present in initial polymer
+    // e.g. ABABC => AB, BA, AB, BC
+    let polymer_pairs = polymer
+        .chars()
+        .tuple_windows::<(_, _)>()
+        .map(&mut index)
+        .collect::<Vec<_>>();
+
+    // Rules indexed by pair (same order as pairs), None if a pair has no rule
+    let mut rules_by_pair = vec![None; pairs.len()];
+    rules
+        .into_iter()
+        .for_each(|(idx, next)| rules_by_pair[idx] = Some(next));
 
     // Count pairs present in initial polymer
     // e.g. ABABC => AB: 2, BA: 1, BC: 1
     let mut pair_count = vec![0; pairs.len()];
-    polymer
-        .chars()
-        .tuple_windows::<(_, _)>()
-        .map(|(p1, p2)| pairs_idx[&(p1, p2)])
+    polymer_pairs
+        .into_iter()
         .for_each(|pair_idx| pair_count[pair_idx] += 1);
 
     // Save the last element of the polymer
     // e.g. ABABC => C
-    let last_element = polymer.chars().last().unwrap();
+    let last_element = polymer.chars().last();
 
     InputVec {
         pairs,
         pair_count,
-        rules,
+        rules: rules_by_pair,
         last_element,
     }
 }
 
-fn solve_v2(input: &InputVec, steps: usize) -> usize {
-    // Count each pair in the polymer
-    let mut pair_count = input.pair_count.clone();
-    let mut next_pair_count = vec![0; pair_count.len()];
+impl PolymerModel for InputVec {
+    type Counts = Vec<u64>;
 
-    for _ in 0..steps {
-        // Calculate next count
-        pair_count
-            .iter_mut()
+    fn initial_counts(&self) -> Self::Counts {
+        self.pair_count.clone()
+    }
+
+    fn step(&self, counts: &Self::Counts) -> Self::Counts {
+        let mut next_pair_count = vec![0; counts.len()];
+        counts
+            .iter()
             .enumerate()
             .filter(|(_, count)| **count > 0)
-            .for_each(|(pair, cur_count)| {
-                let (pair1, pair2) = input.rules[pair];
-                next_pair_count[pair1] += *cur_count;
-                next_pair_count[pair2] += *cur_count;
-                *cur_count = 0;
+            .for_each(|(pair, count)| match self.rules[pair] {
+                Some((pair1, pair2)) => {
+                    next_pair_count[pair1] += count;
+                    next_pair_count[pair2] += count;
+                }
+                None => next_pair_count[pair] += count,
             });
-
-        // Swap counts
-        std::mem::swap(&mut pair_count, &mut next_pair_count);
+        next_pair_count
     }
 
-    // Count each element as # of pairs starting with element
-    let mut element_count: HashMap<char, usize> = HashMap::new();
-    pair_count
-        .into_iter()
-        .enumerate()
-        .for_each(|(pair, count)| {
-            *element_count.entry(input.pairs[pair].0).or_default() += count;
-        });
+    fn histogram_of(&self, counts: &Self::Counts) -> Histogram {
+        // Count each element as # of pairs starting with element
+        let mut element_count = Histogram::new();
+        counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .for_each(|(pair, count)| {
+                *element_count.entry(self.pairs[pair].0).or_default() += count;
+            });
 
-    // Add the last element to the count
-    element_count
-        .entry(input.last_element)
-        .and_modify(|e| *e += 1);
+        // Add the last element to the count
+        if let Some(last) = self.last_element {
+            *element_count.entry(last).or_default() += 1;
+        }
+        element_count
+    }
+}
 
-    element_count.values().max().unwrap() - element_count.values().min().unwrap()
+fn solve_v2(input: &InputVec, steps: usize) -> usize {
+    spread(&input.histogram(steps)) as usize
 }
 
 #[aoc(day14, part1, vec_of_int)]
@@ -127,6 +194,60 @@ pub fn part2_vec_of_int(input: &InputVec) -> usize {
     solve_v2(input, 40)
 }
 
+/// m[j][i] is the number of pairs j produced by a pair i in one step
+fn transition_matrix(input: &InputVec) -> Matrix {
+    let n = input.pairs.len();
+    let mut m = vec![vec![0; n]; n];
+    input
+        .rules
+        .iter()
+        .enumerate()
+        .for_each(|(i, rule)| match rule {
+            Some((pair1, pair2)) => {
+                m[*pair1][i] += 1;
+                m[*pair2][i] += 1;
+            }
+            None => m[i][i] = 1,
+        });
+    m
+}
+
+/// Element histogram after `steps` using fast exponentiation of the pair transition matrix:
+/// O(pairs^3 * log(steps)).
+/// Without `modulus` the counts are exact, and `None` once they overflow `u128`
+/// (after about 120 steps). With it the counts are reduced modulo `modulus`, see [`mat_pow_vec`].
+pub fn histogram_matpow(
+    input: &InputVec,
+    steps: u64,
+    modulus: Option<u128>,
+) -> Option<BTreeMap<char, u128>> {
+    let counts = input
+        .pair_count
+        .iter()
+        .map(|c| *c as u128)
+        .collect::<Vec<_>>();
+    let counts = mat_pow_vec(&transition_matrix(input), steps, &counts, modulus)?;
+
+    let mut element_count = BTreeMap::<char, u128>::new();
+    let last = input.last_element.map(|last| (last, 1));
+    let firsts = counts
+        .into_iter()
+        .enumerate()
+        .filter(|(_, count)| *count > 0)
+        .map(|(pair, count)| (input.pairs[pair].0, count));
+    for (element, count) in firsts.chain(last) {
+        let total = element_count.entry(element).or_default();
+        *total = mul_add(*total, count, 1, modulus)?;
+    }
+    Some(element_count)
+}
+
+#[aoc(day14, part2, matrix)]
+pub fn part2_matrix(input: &InputVec) -> Option<u128> {
+    let histogram = histogram_matpow(input, 40, None)?;
+    Some(histogram.values().max().unwrap_or(&0) - histogram.values().min().unwrap_or(&0))
+}
+
 /// Initial solution with HashMap (slower)
 pub struct InputHashmap {
     polymer: String,
@@ -135,7 +256,7 @@ pub struct InputHashmap {
 
 #[aoc_generator(day14, part2, hashmap_of_string)]
 pub fn input_hm_parser(input: &str) -> InputHashmap {
-    let (polymer, rules) = input.split_once("\n\n").unwrap();
+    let (polymer, rules) = input.split_once("\n\n").unwrap_or((input, ""));
     let rules = rules
         .lines()
         .map(|l| {
@@ -151,55 +272,152 @@ pub fn input_hm_parser(input: &str) -> InputHashmap {
         .collect();
 
     InputHashmap {
-        polymer: polymer.to_string(),
+        polymer: polymer.trim().to_string(),
         rules,
     }
 }
 
-fn solve(input: &InputHashmap, steps: usize) -> u64 {
-    let polymer = &input.polymer;
-
-    // Count each pair in the polymer
-    let mut pair_count: HashMap<String, u64> = input
-        .rules
-        .iter()
-        .map(|(pair, _)| (pair.clone(), 0))
-        .collect();
-    let mut next_pair_count = pair_count.clone();
+impl PolymerModel for InputHashmap {
+    type Counts = HashMap<String, u64>;
 
-    for i in 0..polymer.len() - 1 {
-        *pair_count.get_mut(&polymer[i..i + 2]).unwrap() += 1;
+    fn initial_counts(&self) -> Self::Counts {
+        let polymer = &self.polymer;
+        let mut pair_count = HashMap::new();
+        for i in 0..polymer.len().saturating_sub(1) {
+            *pair_count.entry(polymer[i..i + 2].to_string()).or_default() += 1;
+        }
+        pair_count
     }
 
-    for _ in 0..steps {
-        // Calculate next count
-        pair_count
-            .iter_mut()
-            .filter(|(_, count)| **count > 0)
-            .for_each(|(pair, cur_count)| {
-                if let Some((p1, p2)) = input.rules.get(pair) {
-                    *next_pair_count.get_mut(p1).unwrap() += *cur_count;
-                    *next_pair_count.get_mut(p2).unwrap() += *cur_count;
-                    *cur_count = 0;
+    fn step(&self, counts: &Self::Counts) -> Self::Counts {
+        let mut next_pair_count = HashMap::new();
+        counts
+            .iter()
+            .for_each(|(pair, count)| match self.rules.get(pair) {
+                Some((p1, p2)) => {
+                    *next_pair_count.entry(p1.clone()).or_default() += count;
+                    *next_pair_count.entry(p2.clone()).or_default() += count;
                 }
+                None => *next_pair_count.entry(pair.clone()).or_default() += count,
             });
+        next_pair_count
+    }
+
+    fn histogram_of(&self, counts: &Self::Counts) -> Histogram {
+        // Count each element as # of pairs starting with element
+        let mut element_count = Histogram::new();
+        counts.iter().for_each(|(s, count)| {
+            let el = s.chars().next().unwrap();
+            *element_count.entry(el).or_default() += count;
+        });
+
+        // Add the last element to the count
+        if let Some(last) = self.polymer.chars().last() {
+            *element_count.entry(last).or_default() += 1;
+        }
+        element_count
+    }
+}
+
+impl InputHashmap {
+    /// Polymer after `steps`, one element at a time, expanding pairs depth-first:
+    /// memory grows with `steps` rather than with the polymer length
+    pub fn expand(&self, steps: usize) -> Expansion<'_> {
+        let pairs = (0..self.polymer.len().saturating_sub(1))
+            .rev()
+            .map(|i| (&self.polymer[i..i + 2], steps))
+            .collect();
+        Expansion {
+            rules: &self.rules,
+            first: self.polymer.chars().next(),
+            pairs,
+        }
+    }
 
-        // Swap counts
-        std::mem::swap(&mut pair_count, &mut next_pair_count);
+    /// Number of elements each pair expands to (without its first element) after
+    /// 0, 1, .. `steps` steps, saturating. Pairs without a rule always expand to 1.
+    fn expansion_lengths(&self, steps: usize) -> Vec<HashMap<&str, u64>> {
+        let mut lengths: Vec<HashMap<&str, u64>> = vec![HashMap::new()];
+        for d in 1..=steps {
+            let previous = &lengths[d - 1];
+            let len = |pair: &str| previous.get(pair).copied().unwrap_or(1);
+            let next = self
+                .rules
+                .iter()
+                .map(|(pair, (p1, p2))| (pair.as_str(), len(p1).saturating_add(len(p2))))
+                .collect();
+            lengths.push(next);
+        }
+        lengths
     }
 
-    // Count each element as # of pairs starting with element
-    let mut element_count: HashMap<char, u64> = HashMap::new();
-    pair_count.into_iter().for_each(|(s, count)| {
-        let el = s.chars().next().unwrap();
-        *element_count.entry(el).or_default() += count;
-    });
+    /// Element at `position` in the polymer after `steps`, without expanding it
+    pub fn char_at(&self, steps: usize, position: u64) -> Option<char> {
+        let lengths = self.expansion_lengths(steps);
+        let len = |pair: &str, d: usize| lengths[d].get(pair).copied().unwrap_or(1);
+
+        if position == 0 {
+            return self.polymer.chars().next();
+        }
+        let mut position = position - 1;
+        // Find the pair of the template expanding over the position
+        let mut pair = (0..self.polymer.len().saturating_sub(1))
+            .map(|i| &self.polymer[i..i + 2])
+            .find(|pair| match position.checked_sub(len(pair, steps)) {
+                Some(rest) => {
+                    position = rest;
+                    false
+                }
+                None => true,
+            })?;
+
+        // Then go down its expansion
+        for d in (0..steps).rev() {
+            match self.rules.get(pair) {
+                Some((p1, p2)) => match position.checked_sub(len(p1, d)) {
+                    Some(rest) => {
+                        position = rest;
+                        pair = p2;
+                    }
+                    None => pair = p1,
+                },
+                None => break,
+            }
+        }
+        pair.chars().nth(1)
+    }
+}
 
-    // Add the last element to the count
-    element_count
-        .entry(polymer.chars().last().unwrap())
-        .and_modify(|e| *e += 1);
-    element_count.values().max().unwrap() - element_count.values().min().unwrap()
+pub struct Expansion<'a> {
+    rules: &'a HashMap<String, (String, String)>,
+    first: Option<char>,
+    /// Pairs left to expand with their remaining steps, next one last
+    pairs: Vec<(&'a str, usize)>,
+}
+
+impl Iterator for Expansion<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(first) = self.first.take() {
+            return Some(first);
+        }
+        // Each pair yields its second element once fully expanded
+        while let Some((pair, steps)) = self.pairs.pop() {
+            match self.rules.get(pair) {
+                Some((p1, p2)) if steps > 0 => {
+                    self.pairs.push((p2, steps - 1));
+                    self.pairs.push((p1, steps - 1));
+                }
+                _ => return pair.chars().nth(1),
+            }
+        }
+        None
+    }
+}
+
+fn solve(input: &InputHashmap, steps: usize) -> u64 {
+    spread(&input.histogram(steps))
 }
 
 #[aoc(day14, part2, hashmap_of_string)]
@@ -244,4 +462,104 @@ CN -> C";
         );
         assert_eq!(part2_vec_of_int(&input_vec_parser(TESTCASE)), 2188189693529);
     }
+
+    #[test]
+    fn test_histograms() {
+        let expected = [('B', 1749), ('C', 298), ('H', 161), ('N', 865)];
+        assert_eq!(
+            input_vec_parser(TESTCASE).histogram(10),
+            expected.into_iter().collect()
+        );
+        assert_eq!(
+            input_hm_parser(TESTCASE).histogram(10),
+            expected.into_iter().collect()
+        );
+
+        // NNCB, NCNBCHB, ..
+        let input = input_hm_parser(TESTCASE);
+        let mut histograms = input.histograms();
+        assert_eq!(histograms.next().unwrap()[&'N'], 2);
+        assert_eq!(
+            histograms.next().unwrap(),
+            [('B', 2), ('C', 2), ('H', 1), ('N', 2)]
+                .into_iter()
+                .collect()
+        );
+
+        // NNCB => NCNCB => NCCNCB: only NN and CN have rules
+        let partial = "NNCB\n\nNN -> C\nCN -> C";
+        let expected = [('B', 1), ('C', 3), ('N', 2)].into_iter().collect();
+        assert_eq!(input_vec_parser(partial).histogram(2), expected);
+        assert_eq!(input_hm_parser(partial).histogram(2), expected);
+
+        // No rule at all
+        assert_eq!(part1(&input_vec_parser("NNCB\n\n")), 1);
+        assert_eq!(solve(&input_hm_parser("NNCB"), 40), 1);
+        assert_eq!(part1(&input_vec_parser("")), 0);
+    }
+
+    #[test]
+    fn test_matrix() {
+        let input = input_vec_parser(TESTCASE);
+        assert_eq!(part2_matrix(&input), Some(2188189693529));
+        for steps in [0, 1, 10] {
+            let expected = input
+                .histogram(steps)
+                .into_iter()
+                .map(|(el, c)| (el, c as u128 % 1_000_000_007))
+                .collect::<BTreeMap<_, _>>();
+            assert_eq!(
+                histogram_matpow(&input, steps as u64, Some(1_000_000_007)),
+                Some(expected)
+            );
+        }
+
+        // The polymer length doubles minus one at each step: 3 * 2^steps + 1
+        let p = 1_000_000_007u128;
+        let (mut pow, mut base, mut exp) = (1, 2, 1_000_000_000u64);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                pow = pow * base % p;
+            }
+            base = base * base % p;
+            exp >>= 1;
+        }
+        let length = |steps| {
+            histogram_matpow(&input, steps, Some(1_000_000_007))
+                .unwrap()
+                .values()
+                .sum::<u128>()
+                % 1_000_000_007
+        };
+        assert_eq!(length(1_000_000_000), (3 * pow + 1) % p);
+
+        // Exact counts overflow u128 past 2^126 elements
+        let exact = histogram_matpow(&input, 120, None).unwrap();
+        assert_eq!(exact.values().sum::<u128>(), 3 * (1 << 120) + 1);
+        assert_eq!(histogram_matpow(&input, 130, None), None);
+    }
+
+    #[test]
+    fn test_expansion() {
+        let input = input_hm_parser(TESTCASE);
+        assert_eq!(input.expand(0).collect::<String>(), "NNCB");
+        assert_eq!(input.expand(2).collect::<String>(), "NBCCNBBBCBHCB");
+        assert_eq!(input.expand(10).count(), 3073);
+
+        let polymer = input.expand(5).collect::<Vec<_>>();
+        assert!(polymer
+            .iter()
+            .enumerate()
+            .all(|(k, c)| input.char_at(5, k as u64) == Some(*c)));
+        assert_eq!(input.char_at(5, polymer.len() as u64), None);
+
+        // 3 * 2^40 + 1 elements, the last one staying B
+        assert_eq!(input.char_at(40, 3 << 40), Some('B'));
+        assert_eq!(input.char_at(40, (3 << 40) + 1), None);
+
+        // Pairs without a rule stay as they are
+        let partial = input_hm_parser("NNCB\n\nNN -> C\nCN -> C");
+        assert_eq!(partial.expand(2).collect::<String>(), "NCCNCB");
+        assert_eq!(partial.char_at(2, 3), Some('N'));
+    }
 }
@@ -4,6 +4,7 @@ use std::{
 };
 
 use aoc_runner_derive::{aoc, aoc_generator};
+use termion::{color, style};
 
 pub struct Input {
     graph: Vec<i32>,
@@ -111,6 +112,200 @@ fn dijkstra_scaled_queue(input: &Input, scale: usize) -> i32 {
     dist[dist.len() - 1]
 }
 
+/// Risk map tiled `scale` times in both directions, risk increasing by 1 on each tile
+struct Tiled<'a> {
+    input: &'a Input,
+    scale: usize,
+    width: usize,
+    len: usize,
+}
+
+impl<'a> Tiled<'a> {
+    fn new(input: &'a Input, scale: usize) -> Self {
+        Tiled {
+            input,
+            scale,
+            width: scale * input.width,
+            len: scale * scale * input.graph.len(),
+        }
+    }
+
+    /// Risk of entering a node, at most 9 for any digit
+    fn risk(&self, node: usize) -> i32 {
+        let x = node / self.width;
+        let y = node % self.width;
+        let width = self.input.width;
+        let weight = self.input.graph[(x % width) * width + y % width];
+        let shift = (x / width + y / width) as i32;
+        (weight + shift - 1) % 9 + 1
+    }
+
+    fn neighbors(&self, node: usize) -> impl Iterator<Item = usize> {
+        let (width, len) = (self.width, self.len);
+        let y = node % width;
+        [
+            (node.wrapping_add(1), y != width - 1),
+            (node.wrapping_sub(1), y > 0),
+            (node.wrapping_sub(width), node >= width),
+            (node.wrapping_add(width), node + width < len),
+        ]
+        .into_iter()
+        .filter(|(_, condition)| *condition)
+        .map(|(n, _)| n)
+    }
+
+    /// Manhattan distance to the bottom right corner, a lower bound of the risk as risks are >= 1
+    fn manhattan(&self, node: usize) -> i32 {
+        let height = self.len / self.width;
+        (self.width - 1 - node % self.width + height - 1 - node / self.width) as i32
+    }
+}
+
+/// Lowest risk path from the top left to the bottom right corner
+pub struct Route {
+    pub risk: i32,
+    /// Positions on the tiled map (row-major), both corners included
+    pub path: Vec<usize>,
+    /// Number of nodes popped from the queue, to compare searches
+    pub expanded: usize,
+    scale: usize,
+}
+
+impl Route {
+    /// Tiled map with the path highlighted
+    pub fn render(&self, input: &Input) -> String {
+        let tiled = Tiled::new(input, self.scale);
+        let mut on_path = vec![false; tiled.len];
+        self.path.iter().for_each(|node| on_path[*node] = true);
+
+        let mut s = String::new();
+        for (node, on_path) in on_path.into_iter().enumerate() {
+            let risk = tiled.risk(node);
+            if on_path {
+                s.push_str(&format!(
+                    "{}{}{}{}{}",
+                    style::Bold,
+                    color::Fg(color::Red),
+                    risk,
+                    color::Fg(color::Reset),
+                    style::Reset
+                ));
+            } else {
+                s.push_str(&risk.to_string());
+            }
+            if (node + 1) % tiled.width == 0 {
+                s.push('\n');
+            }
+        }
+        s
+    }
+}
+
+/// Best-first search keeping predecessors, popping nodes by `risk + heuristic`.
+/// With an admissible heuristic (never more than the remaining risk) this is A*,
+/// with a null one Dijkstra.
+fn search(tiled: &Tiled, heuristic: impl Fn(usize) -> i32) -> Route {
+    let target = tiled.len - 1;
+    let mut dist = vec![i32::MAX; tiled.len];
+    let mut prev = vec![usize::MAX; tiled.len];
+    let mut done = vec![false; tiled.len];
+    dist[0] = 0;
+
+    let mut expanded = 0;
+    let mut queue = BinaryHeap::from([(Reverse(heuristic(0)), 0)]);
+    while let Some((_, node)) = queue.pop() {
+        // Skip nodes already reached with a lower risk
+        if done[node] {
+            continue;
+        }
+        done[node] = true;
+        expanded += 1;
+        if node == target {
+            break;
+        }
+
+        for neighbor in tiled.neighbors(node) {
+            let risk = dist[node] + tiled.risk(neighbor);
+            if risk < dist[neighbor] {
+                dist[neighbor] = risk;
+                prev[neighbor] = node;
+                queue.push((Reverse(risk + heuristic(neighbor)), neighbor));
+            }
+        }
+    }
+
+    let mut path = vec![target];
+    while let Some(&node) = path.last().filter(|n| **n != 0) {
+        path.push(prev[node]);
+    }
+    path.reverse();
+
+    Route {
+        risk: dist[target],
+        path,
+        expanded,
+        scale: tiled.scale,
+    }
+}
+
+/// Lowest risk path on the map tiled `scale` times, with Dijkstra
+pub fn shortest_path(input: &Input, scale: usize) -> Route {
+    search(&Tiled::new(input, scale), |_| 0)
+}
+
+/// Same path, with A* and the Manhattan distance to the target as heuristic
+pub fn astar(input: &Input, scale: usize) -> Route {
+    let tiled = Tiled::new(input, scale);
+    search(&tiled, |node| tiled.manhattan(node))
+}
+
+/// Dial's algorithm: Dijkstra with a bucket per risk instead of a heap.
+/// Queued risks are within `max_weight` of the current one, so `max_weight + 1`
+/// buckets used circularly are enough. Nodes are settled when popped at their risk,
+/// making it correct for any weights in `0..=max_weight`.
+fn dial(tiled: &Tiled, max_weight: usize) -> i32 {
+    let target = tiled.len - 1;
+    let mut dist = vec![usize::MAX; tiled.len];
+    dist[0] = 0;
+
+    let mut buckets = vec![Vec::new(); max_weight + 1];
+    buckets[0].push(0);
+    let mut queued = 1;
+    let mut risk = 0;
+    while queued > 0 {
+        let current = risk % buckets.len();
+        while let Some(node) = buckets[current].pop() {
+            queued -= 1;
+            // Stale entry, the node was reached with a lower risk since
+            if dist[node] != risk {
+                continue;
+            }
+            if node == target {
+                return risk as i32;
+            }
+
+            for neighbor in tiled.neighbors(node) {
+                let weight = tiled.risk(neighbor) as usize;
+                debug_assert!(weight <= max_weight, "Risk {} above {}", weight, max_weight);
+                let next = risk + weight;
+                if next < dist[neighbor] {
+                    dist[neighbor] = next;
+                    let bucket = next % buckets.len();
+                    buckets[bucket].push(neighbor);
+                    queued += 1;
+                }
+            }
+        }
+        risk += 1;
+    }
+    i32::MAX
+}
+
+fn dijkstra_scaled_dial(input: &Input, scale: usize) -> i32 {
+    // Tiled risks wrap around to at most 9
+    dial(&Tiled::new(input, scale), 9)
+}
+
 #[aoc(day15, part1)]
 pub fn part1(input: &Input) -> i32 {
     dijkstra_scaled_heap(input, 1)
@@ -126,6 +321,16 @@ pub fn part2_queue(input: &Input) -> i32 {
     dijkstra_scaled_queue(input, 5)
 }
 
+#[aoc(day15, part2, astar)]
+pub fn part2_astar(input: &Input) -> i32 {
+    astar(input, 5).risk
+}
+
+#[aoc(day15, part2, dial)]
+pub fn part2_dial(input: &Input) -> i32 {
+    dijkstra_scaled_dial(input, 5)
+}
+
 #[cfg(test)]
 mod test_day15 {
     use super::*;
@@ -151,4 +356,58 @@ mod test_day15 {
     fn test_part2() {
         assert_eq!(part2(&input_parser(TESTCASE)), 315)
     }
+
+    #[test]
+    fn test_path() {
+        let input = input_parser(TESTCASE);
+        for scale in [1, 5] {
+            let dijkstra = shortest_path(&input, scale);
+            let astar = astar(&input, scale);
+            assert_eq!(dijkstra.risk, dijkstra_scaled_queue(&input, scale));
+            assert_eq!(astar.risk, dijkstra.risk);
+            assert!(astar.expanded <= dijkstra.expanded);
+
+            // The path is made of adjacent cells adding up to the risk
+            let tiled = Tiled::new(&input, scale);
+            for route in [dijkstra, astar] {
+                assert_eq!(route.path[0], 0);
+                assert_eq!(route.path[route.path.len() - 1], tiled.len - 1);
+                assert!(route
+                    .path
+                    .windows(2)
+                    .all(|w| tiled.neighbors(w[0]).any(|n| n == w[1])));
+                let risk = route.path[1..].iter().map(|n| tiled.risk(*n)).sum::<i32>();
+                assert_eq!(risk, route.risk);
+            }
+        }
+
+        let route = shortest_path(&input, 1);
+        let rendering = route.render(&input);
+        assert_eq!(rendering.lines().count(), 10);
+        assert_eq!(
+            rendering.matches(&style::Bold.to_string()).count(),
+            route.path.len()
+        );
+    }
+
+    #[test]
+    fn test_dial() {
+        let input = input_parser(TESTCASE);
+        assert_eq!(dijkstra_scaled_dial(&input, 1), 40);
+        assert_eq!(part2_dial(&input), 315);
+
+        // Zero weights and a bound above the actual risks
+        let input = input_parser("1099\n9009\n9000");
+        let tiled = Tiled::new(&input, 1);
+        assert_eq!(dial(&tiled, 9), 0);
+        assert_eq!(dial(&tiled, 20), 0);
+        assert_eq!(dijkstra_scaled_dial(&input_parser("19\n11"), 2), 11);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Risk 9 above 5")]
+    fn test_dial_bound() {
+        dial(&Tiled::new(&input_parser("19\n11"), 1), 5);
+    }
 }
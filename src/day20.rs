@@ -3,6 +3,8 @@ use std::fmt::Display;
 use aoc_runner_derive::{aoc, aoc_generator};
 use hashbrown::HashMap;
 
+use crate::utils::{write_pnm, PnmFormat};
+
 type Algorithm = [bool; 512];
 type PixelMap = HashMap<(i32, i32), bool>;
 
@@ -29,6 +31,18 @@ impl Image {
     fn swap_buffer(&mut self) {
         std::mem::swap(&mut self.pixels, &mut self.buffer);
     }
+
+    /// Export the current bounding box as an image, lit pixels at full intensity
+    pub fn write_pnm<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        format: PnmFormat,
+    ) -> std::io::Result<()> {
+        let pixels = (self.ymin..self.ymax)
+            .flat_map(|y| (self.xmin..self.xmax).map(move |x| self.get_pixel(x, y) as u32))
+            .collect::<Vec<_>>();
+        write_pnm(w, &pixels, (self.xmax - self.xmin) as usize, format)
+    }
 }
 
 impl Display for Image {
@@ -155,4 +169,15 @@ mod test_day20 {
     fn test_part2() {
         assert_eq!(part2(&input_parser(TESTCASE)), 3351)
     }
+
+    #[test]
+    fn test_pnm() {
+        let (_, image) = input_parser(TESTCASE);
+        let mut ppm = Vec::new();
+        image.write_pnm(&mut ppm, PnmFormat::Ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n5 5\n255\n"));
+        assert_eq!(ppm.len(), "P6\n5 5\n255\n".len() + 75);
+        // Top left pixel is lit
+        assert_eq!(ppm[ppm.len() - 75..][..3], [255, 255, 255]);
+    }
 }
@@ -5,9 +5,11 @@ use std::{thread, time};
 use aoc_runner_derive::{aoc, aoc_generator};
 use termion::{color, cursor, screen};
 
+use crate::utils::{detect_cycle, Cycle};
+
 use Node::*;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Node {
     Free,
     South,
@@ -76,6 +78,35 @@ impl Map {
     }
 }
 
+/// Maps are the same when their cells are, whatever is left in the step buffers
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width && self.map == other.map
+    }
+}
+
+impl Eq for Map {}
+
+impl std::hash::Hash for Map {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.width.hash(state);
+        self.map.hash(state);
+    }
+}
+
+/// Next state of the map, for cycle detection
+pub fn next_map(map: &Map) -> Map {
+    let mut map = map.clone();
+    map.step();
+    map
+}
+
+/// Pre-period and period of the map states, within about `max_steps`.
+/// A map which stops moving has a period of 1.
+pub fn map_cycle(map: &Map, max_steps: usize) -> Option<Cycle<Map>> {
+    detect_cycle(map.clone(), next_map, max_steps)
+}
+
 impl std::fmt::Display for Map {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut s = String::new();
@@ -162,6 +193,14 @@ pub fn part1(map: &Map) -> usize {
     final_step
 }
 
+/// Same as part1 but `None` for maps which keep moving forever instead of hanging
+#[aoc(day25, part1, cycle)]
+pub fn part1_cycle(map: &Map) -> Option<usize> {
+    let cycle = map_cycle(map, usize::MAX)?;
+    // First step with no movement is the one repeating the start of the cycle
+    (cycle.period == 1).then(|| cycle.start + 1)
+}
+
 #[cfg(test)]
 mod test_day25 {
     use super::*;
@@ -181,4 +220,18 @@ v.v..>>v.v
     fn test_part1() {
         assert_eq!(part1(&input_parser(TESTCASE)), 58)
     }
+
+    #[test]
+    fn test_cycle() {
+        assert_eq!(part1_cycle(&input_parser(TESTCASE)), Some(58));
+
+        // A lone cucumber going round forever
+        let cycle = map_cycle(&input_parser(">..\n...\n..."), 100).unwrap();
+        assert_eq!((cycle.start, cycle.period), (0, 3));
+        assert_eq!(part1_cycle(&input_parser(">..\n...\n...")), None);
+        assert_eq!(
+            cycle.state_at(1_000_000_000_000, next_map).to_string(),
+            " > \n   \n   \n"
+        );
+    }
 }
@@ -1,6 +1,8 @@
 #[macro_use]
 mod utils;
 
+pub use utils::{Cycle, PnmFormat};
+
 pub mod day01;
 pub mod day02;
 pub mod day03;
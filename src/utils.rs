@@ -25,3 +25,315 @@ pub fn print_grid<T: Copy + std::fmt::Display>(t: &[T], s: usize) {
     }
     println!();
 }
+
+/// Netpbm flavour used by [`write_pnm`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PnmFormat {
+    /// Greyscale (P5)
+    Pgm,
+    /// Colour-mapped (P6), black -> red -> yellow -> white
+    Ppm,
+}
+
+/// Heat colour ramp for an intensity in 0..=255
+fn heat_color(v: u8) -> [u8; 3] {
+    let v = v as u32 * 3;
+    let channel = |offset: u32| v.saturating_sub(offset).min(255) as u8;
+    [channel(0), channel(255), channel(510)]
+}
+
+/// Write a row-major grid of values as a binary PGM/PPM image.
+/// Values are scaled so that the largest one maps to full intensity.
+pub fn write_pnm<W: std::io::Write>(
+    w: &mut W,
+    values: &[u32],
+    width: usize,
+    format: PnmFormat,
+) -> std::io::Result<()> {
+    let height = values.len().checked_div(width).unwrap_or(0);
+    let max = values.iter().copied().max().unwrap_or(0).max(1) as u64;
+    let scaled = values.iter().map(|&v| (v as u64 * 255 / max) as u8);
+    match format {
+        PnmFormat::Pgm => {
+            write!(w, "P5\n{} {}\n255\n", width, height)?;
+            w.write_all(&scaled.collect::<Vec<_>>())
+        }
+        PnmFormat::Ppm => {
+            write!(w, "P6\n{} {}\n255\n", width, height)?;
+            w.write_all(&scaled.flat_map(heat_color).collect::<Vec<_>>())
+        }
+    }
+}
+
+/// Square matrix of counts, `m[to][from]`
+pub type Matrix = Vec<Vec<u128>>;
+
+/// `acc + a * b`, reduced modulo `modulus`, or `None` on overflow without it
+pub fn mul_add(acc: u128, a: u128, b: u128, modulus: Option<u128>) -> Option<u128> {
+    match modulus {
+        Some(p) => Some((acc + a * b) % p),
+        None => acc.checked_add(a.checked_mul(b)?),
+    }
+}
+
+fn mat_mul(a: &Matrix, b: &Matrix, modulus: Option<u128>) -> Option<Matrix> {
+    let n = a.len();
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| (0..n).try_fold(0, |acc, k| mul_add(acc, a[i][k], b[k][j], modulus)))
+                .collect()
+        })
+        .collect()
+}
+
+fn mat_vec(m: &Matrix, v: &[u128], modulus: Option<u128>) -> Option<Vec<u128>> {
+    m.iter()
+        .map(|row| {
+            row.iter()
+                .zip(v)
+                .try_fold(0, |acc, (a, b)| mul_add(acc, *a, *b, modulus))
+        })
+        .collect()
+}
+
+/// `m^exp * v` by repeated squaring: O(n^3 * log(exp)).
+/// Without `modulus` the result is exact, and `None` once a count overflows `u128`.
+/// With it counts are reduced modulo `modulus`, which must be in `1..2^64` so that
+/// products of reduced values can't overflow.
+pub fn mat_pow_vec(m: &Matrix, exp: u64, v: &[u128], modulus: Option<u128>) -> Option<Vec<u128>> {
+    if let Some(p) = modulus {
+        assert!(
+            p > 0 && p <= u64::MAX as u128,
+            "Modulus should be in 1..2^64"
+        );
+    }
+    let mut v = v
+        .iter()
+        .map(|c| modulus.map_or(*c, |p| c % p))
+        .collect::<Vec<_>>();
+    let mut base = m.clone();
+    let mut exp = exp;
+    // Powers of the same matrix commute, so they can be applied to the vector directly
+    while exp > 0 {
+        if exp & 1 == 1 {
+            v = mat_vec(&base, &v, modulus)?;
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = mat_mul(&base, &base, modulus)?;
+        }
+    }
+    Some(v)
+}
+
+/// Cycle of a deterministic simulation: states repeat with `period` from step `start` on
+#[derive(Clone, Debug)]
+pub struct Cycle<S> {
+    pub start: usize,
+    pub period: usize,
+    initial: S,
+    first_repeated: S,
+}
+
+fn fingerprint<S: std::hash::Hash>(state: &S) -> u64 {
+    use std::hash::Hasher;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Brent's cycle detection on the states reached from `initial` by `step`.
+/// States are compared by hash first, then by equality.
+/// Gives up with `None` if no cycle is found within about `max_steps` steps.
+pub fn detect_cycle<S, F>(initial: S, step: F, max_steps: usize) -> Option<Cycle<S>>
+where
+    S: Clone + Eq + std::hash::Hash,
+    F: Fn(&S) -> S,
+{
+    let same = |a: &(u64, S), b: &(u64, S)| a.0 == b.0 && a.1 == b.1;
+    let advance = |s: &(u64, S)| {
+        let next = step(&s.1);
+        (fingerprint(&next), next)
+    };
+
+    // Find the period: the hare runs ahead by powers of two
+    let start = (fingerprint(&initial), initial.clone());
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = start.clone();
+    let mut hare = advance(&tortoise);
+    let mut steps = 1;
+    while !same(&tortoise, &hare) {
+        if steps >= max_steps {
+            return None;
+        }
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = advance(&hare);
+        period += 1;
+        steps += 1;
+    }
+
+    // Find the start: both move at the same pace, `period` steps apart
+    let mut tortoise = start.clone();
+    let mut hare = start;
+    for _ in 0..period {
+        hare = advance(&hare);
+    }
+    let mut cycle_start = 0;
+    while !same(&tortoise, &hare) {
+        tortoise = advance(&tortoise);
+        hare = advance(&hare);
+        cycle_start += 1;
+    }
+
+    Some(Cycle {
+        start: cycle_start,
+        period,
+        initial,
+        first_repeated: tortoise.1,
+    })
+}
+
+impl<S: Clone> Cycle<S> {
+    /// State after `n` steps, stepping at most `max(start, period)` times
+    pub fn state_at<F: Fn(&S) -> S>(&self, n: usize, step: F) -> S {
+        let (mut state, remaining) = if n < self.start {
+            (self.initial.clone(), n)
+        } else {
+            (self.first_repeated.clone(), (n - self.start) % self.period)
+        };
+        for _ in 0..remaining {
+            state = step(&state);
+        }
+        state
+    }
+}
+
+/// Letters of the 4x6 font used by most puzzles printing letters
+const FONT_SMALL: [(char, &str); 18] = [
+    ('A', ".##./#..#/#..#/####/#..#/#..#"),
+    ('B', "###./#..#/###./#..#/#..#/###."),
+    ('C', ".##./#..#/#.../#.../#..#/.##."),
+    ('E', "####/#.../###./#.../#.../####"),
+    ('F', "####/#.../###./#.../#.../#..."),
+    ('G', ".##./#..#/#.../#.##/#..#/.###"),
+    ('H', "#..#/#..#/####/#..#/#..#/#..#"),
+    ('I', "###/.#./.#./.#./.#./###"),
+    ('J', "..##/...#/...#/...#/#..#/.##."),
+    ('K', "#..#/#.#./##../#.#./#.#./#..#"),
+    ('L', "#.../#.../#.../#.../#.../####"),
+    ('O', ".##./#..#/#..#/#..#/#..#/.##."),
+    ('P', "###./#..#/#..#/###./#.../#..."),
+    ('R', "###./#..#/#..#/###./#.#./#..#"),
+    ('S', ".###/#.../#.../.##./...#/###."),
+    ('U', "#..#/#..#/#..#/#..#/#..#/.##."),
+    ('Y', "#...#/#...#/.#.#./..#../..#../..#.."),
+    ('Z', "####/...#/..#./.#../#.../####"),
+];
+
+/// Letters of the 6x10 font
+const FONT_LARGE: [(char, &str); 15] = [
+    (
+        'A',
+        "..##../.#..#./#....#/#....#/#....#/######/#....#/#....#/#....#/#....#",
+    ),
+    (
+        'B',
+        "#####./#....#/#....#/#....#/#####./#....#/#....#/#....#/#....#/#####.",
+    ),
+    (
+        'C',
+        ".####./#....#/#...../#...../#...../#...../#...../#...../#....#/.####.",
+    ),
+    (
+        'E',
+        "######/#...../#...../#...../#####./#...../#...../#...../#...../######",
+    ),
+    (
+        'F',
+        "######/#...../#...../#...../#####./#...../#...../#...../#...../#.....",
+    ),
+    (
+        'G',
+        ".####./#....#/#...../#...../#...../#..###/#....#/#....#/#...##/.###.#",
+    ),
+    (
+        'H',
+        "#....#/#....#/#....#/#....#/######/#....#/#....#/#....#/#....#/#....#",
+    ),
+    (
+        'J',
+        "...###/....#./....#./....#./....#./....#./....#./#...#./#...#./.###..",
+    ),
+    (
+        'K',
+        "#....#/#...#./#..#../#.#.../##..../##..../#.#.../#..#../#...#./#....#",
+    ),
+    (
+        'L',
+        "#...../#...../#...../#...../#...../#...../#...../#...../#...../######",
+    ),
+    (
+        'N',
+        "#....#/##...#/##...#/#.#..#/#.#..#/#..#.#/#..#.#/#...##/#...##/#....#",
+    ),
+    (
+        'P',
+        "#####./#....#/#....#/#....#/#####./#...../#...../#...../#...../#.....",
+    ),
+    (
+        'R',
+        "#####./#....#/#....#/#....#/#####./#..#../#...#./#...#./#....#/#....#",
+    ),
+    (
+        'X',
+        "#....#/#....#/.#..#./.#..#./..##../..##../.#..#./.#..#./#....#/#....#",
+    ),
+    (
+        'Z',
+        "######/.....#/.....#/....#./...#../..#.../.#..../#...../#...../######",
+    ),
+];
+
+/// Read letters from rows of lit pixels, in the 4x6 or 6x10 font depending on the height.
+/// Letters are split on blank columns, `None` if any of them isn't recognised.
+pub fn ocr(rows: &[Vec<bool>]) -> Option<String> {
+    // Trim blank rows
+    let first = rows.iter().position(|r| r.contains(&true))?;
+    let last = rows.iter().rposition(|r| r.contains(&true))?;
+    let rows = &rows[first..=last];
+    let font: &[(char, &str)] = match rows.len() {
+        6 => &FONT_SMALL,
+        10 => &FONT_LARGE,
+        _ => return None,
+    };
+
+    let width = rows.iter().map(|r| r.len()).max()?;
+    let lit = |x: usize| rows.iter().any(|r| r.get(x) == Some(&true));
+    let mut text = String::new();
+    let mut x = 0;
+    while x < width {
+        if !lit(x) {
+            x += 1;
+            continue;
+        }
+        let end = (x..width).find(|x| !lit(*x)).unwrap_or(width);
+        let glyph = rows
+            .iter()
+            .map(|r| {
+                (x..end)
+                    .map(|x| if r.get(x) == Some(&true) { '#' } else { '.' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        text.push(font.iter().find(|(_, g)| *g == glyph)?.0);
+        x = end;
+    }
+    Some(text)
+}